@@ -0,0 +1,34 @@
+use std::{
+	collections::hash_map::RandomState,
+	hash::{BuildHasher, Hasher},
+	time::Duration,
+};
+
+/// Exponential backoff with jitter. The delay doubles with every attempt until it reaches `max` and a random
+/// part of up to half of it is taken off, so retries of many blocks don't hit the node at the same moment.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+	pub base: Duration,
+	pub max: Duration,
+}
+
+impl Backoff {
+	pub const fn new(base: Duration, max: Duration) -> Self {
+		Self { base, max }
+	}
+
+	/// Delay before retry number `attempt`. The first retry is attempt 1.
+	pub fn delay(&self, attempt: u32) -> Duration {
+		let exponent = attempt.saturating_sub(1).min(31);
+		let delay = self.base.saturating_mul(1u32 << exponent).min(self.max);
+
+		let half = delay.as_millis() as u64 / 2;
+		let jitter = if half > 0 { random_u64() % (half + 1) } else { 0 };
+		delay.saturating_sub(Duration::from_millis(jitter))
+	}
+}
+
+/// Randomly seeded hasher output. Good enough for jitter and doesn't need an extra dependency.
+pub fn random_u64() -> u64 {
+	RandomState::new().build_hasher().finish()
+}
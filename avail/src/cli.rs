@@ -0,0 +1,108 @@
+/// What the binary should do. Selected by the first command line argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+	/// Applies pending migrations and indexes blocks forever. Used when no argument is passed.
+	Run,
+	/// Applies pending migrations and exits.
+	Migrate,
+	/// Applies pending migrations, rewrites message ids that were stored wrapped to negative numbers and exits.
+	RepairIds,
+	/// Compares stored blocks between `from` and `to` (both inclusive) against the chain and exits.
+	/// With `repair` set, blocks that differ are rewritten.
+	Verify { from: u32, to: u32, repair: bool },
+	/// Indexes blocks between `from` and `to` (both inclusive) and exits once all of them are committed.
+	Backfill { from: u32, to: u32 },
+}
+
+impl Command {
+	pub fn from_args() -> Result<Self, String> {
+		let args: Vec<String> = std::env::args().skip(1).collect();
+		match args.first().map(|x| x.as_str()) {
+			None | Some("run") => Ok(Self::Run),
+			Some("migrate") => Ok(Self::Migrate),
+			Some("repair-ids") => Ok(Self::RepairIds),
+			Some("verify") => {
+				let (from, to, repair) = parse_range("verify", &args[1..], true)?;
+				Ok(Self::Verify { from, to, repair })
+			},
+			Some("backfill") => {
+				let (from, to, _) = parse_range("backfill", &args[1..], false)?;
+				Ok(Self::Backfill { from, to })
+			},
+			Some(other) => Err(std::format!(
+				"Unknown command: {}. Expected one of: run, migrate, repair-ids, verify, backfill",
+				other
+			)),
+		}
+	}
+}
+
+/// <command> --from <height> --to <height> [--repair]
+fn parse_range(command: &str, args: &[String], allow_repair: bool) -> Result<(u32, u32, bool), String> {
+	let mut from = None;
+	let mut to = None;
+	let mut repair = false;
+
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--from" => from = Some(parse_height("--from", args.next())?),
+			"--to" => to = Some(parse_height("--to", args.next())?),
+			"--repair" if allow_repair => repair = true,
+			other => return Err(std::format!("Unknown {} argument: {}", command, other)),
+		}
+	}
+
+	let Some(from) = from else {
+		return Err(std::format!("{} requires --from", command));
+	};
+	let Some(to) = to else {
+		return Err(std::format!("{} requires --to", command));
+	};
+	if from > to {
+		return Err(std::format!("--from ({}) must not be greater than --to ({})", from, to));
+	}
+
+	Ok((from, to, repair))
+}
+
+fn parse_height(flag: &str, value: Option<&String>) -> Result<u32, String> {
+	let Some(value) = value else {
+		return Err(std::format!("{} requires a block height", flag));
+	};
+	value
+		.parse::<u32>()
+		.map_err(|e| std::format!("Failed to parse {} as u32. {}", flag, e))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn args(values: &[&str]) -> Vec<String> {
+		values.iter().map(|x| x.to_string()).collect()
+	}
+
+	#[test]
+	fn parse_range_reads_both_heights() {
+		let range = parse_range("backfill", &args(&["--from", "10", "--to", "20"]), false);
+		assert_eq!(range, Ok((10, 20, false)));
+
+		let range = parse_range("verify", &args(&["--to", "10", "--repair", "--from", "10"]), true);
+		assert_eq!(range, Ok((10, 10, true)));
+	}
+
+	#[test]
+	fn parse_range_rejects_from_above_to() {
+		assert!(parse_range("backfill", &args(&["--from", "21", "--to", "20"]), false).is_err());
+	}
+
+	#[test]
+	fn parse_range_rejects_missing_and_unknown_arguments() {
+		assert!(parse_range("backfill", &args(&["--from", "10"]), false).is_err());
+		assert!(parse_range("backfill", &args(&["--to", "10"]), false).is_err());
+		assert!(parse_range("backfill", &args(&["--from"]), false).is_err());
+		assert!(parse_range("backfill", &args(&["--from", "-1", "--to", "10"]), false).is_err());
+		assert!(parse_range("backfill", &args(&["--from", "1", "--to", "10", "--repair"]), false).is_err());
+	}
+}
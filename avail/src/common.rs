@@ -1,18 +1,26 @@
-use crate::db::{self, DataForDatabase};
+use crate::{
+	db::{self, DataForDatabase},
+	handler::ExtrinsicContext,
+	indexer::TaskError,
+	registry::CallRegistry,
+};
 use avail_rust::{
-	ExtrinsicDecodable, H256, HasHeader,
+	H256, HasHeader,
 	avail::{
+		system::events::{ExtrinsicFailed, ExtrinsicSuccess},
 		timestamp::tx::Set,
+		transaction_payment::events::TransactionFeePaid,
 		vector::{
+			events::{MessageExecuted, MessageSubmitted},
 			tx::{Execute, FailedSendMessageTxs, SendMessage},
 			types::{AddressedMessage, Message},
 		},
 	},
 	block,
-	block::{BlockEncodedExtrinsicsQuery, BlockEvents, BlockEventsQuery, BlockExtrinsic, extrinsic_options::Options},
-	ext::const_hex,
+	block::{BlockEncodedExtrinsic, BlockEvent, BlockEvents, BlockEventsQuery, BlockExtrinsic},
+	ext::{codec::Decode, const_hex},
 };
-use tracing::{info, warn};
+use tracing::warn;
 
 /// Possible types of Messages allowed by Avail to bridge to other chains.
 #[derive(Debug, Clone, serde::Serialize)]
@@ -45,6 +53,21 @@ impl SerializedMessage {
 			SerializedMessage::FungibleToken { asset_id: _, amount } => Some(amount.clone()),
 		}
 	}
+
+	pub fn asset_id(&self) -> Option<H256> {
+		match self {
+			SerializedMessage::ArbitraryMessage(_) => None,
+			SerializedMessage::FungibleToken { asset_id, amount: _ } => Some(*asset_id),
+		}
+	}
+
+	/// Hex encoded payload of an Arbitrary Message
+	pub fn payload(&self) -> Option<String> {
+		match self {
+			SerializedMessage::ArbitraryMessage(payload) => Some(payload.clone()),
+			SerializedMessage::FungibleToken { asset_id: _, amount: _ } => None,
+		}
+	}
 }
 
 /// Message type used to bridge between Avail & other chains
@@ -129,111 +152,216 @@ impl From<Execute> for SerializedExecute {
 	}
 }
 
+/// Failing to fetch the events of an extrinsic fails the whole block, as storing it without them would lose its
+/// `ext_success`.
 pub async fn convert_extrinsics_to_table_entries(
 	node: &avail_rust::Client,
+	registry: &CallRegistry,
 	list: Vec<block::BlockEncodedExtrinsic>,
 	block_height: u32,
 	block_hash: H256,
 	block_timestamp: u64,
-	failed_txs: Vec<u32>,
-) -> Result<DataForDatabase, String> {
+	failed_txs: Option<Vec<u32>>,
+) -> Result<DataForDatabase, TaskError> {
 	let mut db_data = DataForDatabase::default();
 
 	let mut events_query = BlockEventsQuery::new(node.clone(), block_hash);
 	events_query.set_retry_on_error(Some(false));
 
 	for ext in list {
+		let Some(call) = registry.get((ext.metadata.pallet_id, ext.metadata.variant_id)) else {
+			continue;
+		};
+
 		let mut main_entry =
 			db::main_table::TableEntry::from_block_ext(block_height, block_hash, block_timestamp, &ext);
 
-		let events = events_query
-			.extrinsic(ext.ext_index())
-			.await
-			.unwrap_or_else(|_| BlockEvents::new(Vec::new()));
+		let events = events_query.extrinsic(ext.ext_index()).await.map_err(|e| {
+			TaskError::Transient(std::format!(
+				"Failed to fetch events of extrinsic {}. Error: {}",
+				ext.ext_index(),
+				e.to_string()
+			))
+		})?;
 		if !events.is_empty() {
 			main_entry.ext_success = Some(events.is_extrinsic_success_present())
 		}
 
-		let extrinsic_index = main_entry.ext_index;
-		let id = main_entry.id;
-		if let Ok(send_message) = SendMessage::from_call(&ext.call) {
-			if failed_txs.contains(&ext.metadata.ext_index) {
-				warn!(
-					block_height,
-					extrinsic_index = main_entry.ext_index,
-					skipped = true,
-					"✉️  Send Message found but skipped as it ext index is in failed txs list",
-				);
-				continue;
+		let event_entries = convert_events_to_table_entries(main_entry.id, &events);
+		let ctx = ExtrinsicContext {
+			ext: &ext,
+			events: &events,
+			failed_txs: failed_txs.as_deref(),
+			main_entry,
+			event_entries,
+		};
+
+		// The handler doesn't understand the call, most likely after a runtime upgrade.
+		if let Err(err) = call.handler.process(ctx, &mut db_data) {
+			let error = std::format!("{}: {}", call.name, err);
+			push_dead_letter(&mut db_data, block_height, block_hash, &ext, error);
+		}
+	}
+
+	Ok(db_data)
+}
+
+/// Keeps the raw call of an extrinsic that can't be stored so it can be processed again later. The rest of the
+/// block is indexed as usual.
+fn push_dead_letter(
+	db_data: &mut DataForDatabase,
+	block_height: u32,
+	block_hash: H256,
+	ext: &BlockEncodedExtrinsic,
+	error: String,
+) {
+	warn!(
+		block_height,
+		extrinsic_index = ext.metadata.ext_index,
+		error,
+		"💀 Failed to process extrinsic. Storing it in the dead letter table"
+	);
+	let entry = db::dead_letter_table::TableEntry::from_block_ext(block_height, block_hash, ext, error);
+	db_data.dead_letter_entries.push(entry);
+}
+
+/// Every event emitted by the extrinsic becomes an entry. Events that we know how to decode
+/// get their name and fields as well.
+pub fn convert_events_to_table_entries(id: u64, events: &BlockEvents) -> Vec<db::event_table::TableEntry> {
+	events
+		.events
+		.iter()
+		.map(|event| {
+			let decoded = decode_event_fields(event);
+			db::event_table::TableEntry {
+				id,
+				event_index: event.index,
+				pallet_id: event.pallet_id,
+				variant_id: event.variant_id,
+				event_name: decoded.as_ref().map(|x| x.0),
+				fields: decoded.map(|x| x.1),
 			}
+		})
+		.collect()
+}
 
-			info!(block_height, extrinsic_index, "✉️  Send Message",);
-			let serialized_call = SerializedSendMessage::from(send_message);
-			let extra_entry = db::send_message_table::TableEntry::from_call(id, &serialized_call);
+fn decode_event_fields(event: &BlockEvent) -> Option<(&'static str, serde_json::Value)> {
+	let header = (event.pallet_id, event.variant_id);
 
-			main_entry.ext_call = serialized_call.to_json()?;
-			db_data.main_entries.push(main_entry);
-			db_data.send_message_entries.push(extra_entry);
+	if header == MessageSubmitted::HEADER_INDEX {
+		let value = decode_event::<MessageSubmitted>(event)?;
+		let fields = serde_json::json!({
+			"from": value.from.to_string(),
+			"to": std::format!("{:?}", value.to),
+			"message_type": std::format!("{:?}", value.message_type),
+			"destination_domain": value.destination_domain,
+			"message_id": value.message_id,
+		});
+		return Some(("Vector::MessageSubmitted", fields));
+	}
 
-			continue;
-		}
+	if header == MessageExecuted::HEADER_INDEX {
+		let value = decode_event::<MessageExecuted>(event)?;
+		let fields = serde_json::json!({
+			"from": std::format!("{:?}", value.from),
+			"to": value.to.to_string(),
+			"message_id": value.message_id,
+			"message_root": std::format!("{:?}", value.message_root),
+		});
+		return Some(("Vector::MessageExecuted", fields));
+	}
 
-		if let Ok(execute) = Execute::from_call(&ext.call) {
-			info!(block_height, extrinsic_index, "☠️  Execute",);
-			let serialized_call = SerializedExecute::from(execute);
-			let extra_entry = db::execute_table::TableEntry::from_call(id, &serialized_call);
+	if header == TransactionFeePaid::HEADER_INDEX {
+		// u128 does not fit into a JSON number so amounts are stored as strings
+		let value = decode_event::<TransactionFeePaid>(event)?;
+		let fields = serde_json::json!({
+			"who": value.who.to_string(),
+			"actual_fee": value.actual_fee.to_string(),
+			"tip": value.tip.to_string(),
+		});
+		return Some(("TransactionPayment::TransactionFeePaid", fields));
+	}
 
-			main_entry.ext_call = serialized_call.to_json()?;
-			db_data.main_entries.push(main_entry);
-			db_data.execute_entries.push(extra_entry);
+	if header == ExtrinsicFailed::HEADER_INDEX {
+		let value = decode_event::<ExtrinsicFailed>(event)?;
+		let fields = serde_json::json!({
+			"dispatch_error": std::format!("{:?}", value.dispatch_error),
+			"dispatch_info": std::format!("{:?}", value.dispatch_info),
+		});
+		return Some(("System::ExtrinsicFailed", fields));
+	}
 
-			continue;
-		}
+	if header == ExtrinsicSuccess::HEADER_INDEX {
+		let value = decode_event::<ExtrinsicSuccess>(event)?;
+		let fields = serde_json::json!({
+			"dispatch_info": std::format!("{:?}", value.dispatch_info),
+		});
+		return Some(("System::ExtrinsicSuccess", fields));
 	}
 
-	Ok(db_data)
+	None
 }
 
-pub async fn fetch_block_timestamp_and_failed_txs(
-	node: avail_rust::Client,
-	block_hash: H256,
-) -> Result<(u64, Vec<u32>), String> {
-	let tracked_calls: Vec<(u8, u8)> = vec![Set::HEADER_INDEX, FailedSendMessageTxs::HEADER_INDEX];
+pub(crate) fn find_event<T: HasHeader + Decode>(events: &BlockEvents) -> Option<T> {
+	events
+		.events
+		.iter()
+		.filter(|x| (x.pallet_id, x.variant_id) == T::HEADER_INDEX)
+		.find_map(|x| decode_event::<T>(x))
+}
+
+/// Event data is hex and SCALE encoded
+fn decode_event<T: Decode>(event: &BlockEvent) -> Option<T> {
+	let data = const_hex::decode(&event.data).ok()?;
+	T::decode(&mut data.as_slice()).ok()
+}
 
-	let query = BlockEncodedExtrinsicsQuery::new(node, block_hash.into());
-	let opts = Options::default().filter(tracked_calls);
-	let raw_exts = query.all(opts).await.map_err(|e| {
-		std::format!("Failed to fetch block timestamp and failed txs extrinsics. Error: {}", e.to_string())
-	})?;
+/// Fetched together with the tracked calls of every block
+pub const INHERENTS: [(u8, u8); 2] = [Set::HEADER_INDEX, FailedSendMessageTxs::HEADER_INDEX];
 
-	let set_tx = raw_exts
-		.iter()
-		.find(|x| (x.metadata.pallet_id, x.metadata.variant_id) == Set::HEADER_INDEX);
-	let Some(set_tx) = set_tx else {
-		return Err(std::format!("Failed to fetch and find Timestamp::Set extrinsic"));
+/// Reads the block timestamp in seconds and the indices of the Send Messages that Vector reported as failed from
+/// the inherents in `list`. Genesis has no Timestamp::Set and blocks before the Vector pallet existed have no
+/// Vector::FailedSendMessageTxs, so a missing inherent is None. An inherent that can't be decoded won't decode
+/// after refetching either, so it fails the block permanently and is kept as a dead letter.
+pub fn read_block_inherents(
+	list: &[BlockEncodedExtrinsic],
+	block_height: u32,
+	block_hash: H256,
+) -> Result<(Option<u64>, Option<Vec<u32>>), TaskError> {
+	let permanent = |error: String, ext: &BlockEncodedExtrinsic| {
+		let dead_letter =
+			db::dead_letter_table::TableEntry::from_block_ext(block_height, block_hash, ext, error.clone());
+		TaskError::Permanent { block_hash, error, dead_letter: Some(Box::new(dead_letter)) }
 	};
-	let set_tx = match BlockExtrinsic::<Set>::try_from(set_tx) {
-		Ok(x) => x,
-		Err(err) => {
-			return Err(std::format!("Failed convert raw Timestamp::Set to normal extrinsic. Reason: {}", err));
-		},
+	let find = |header: (u8, u8)| {
+		list.iter()
+			.find(|x| (x.metadata.pallet_id, x.metadata.variant_id) == header)
 	};
 
-	let failed_tx = raw_exts
-		.iter()
-		.find(|x| (x.metadata.pallet_id, x.metadata.variant_id) == FailedSendMessageTxs::HEADER_INDEX);
-	let Some(failed_tx) = failed_tx else {
-		return Err(std::format!("Failed to fetch and find Vector::FailedSendMessageTxs extrinsic"));
+	let timestamp = match find(Set::HEADER_INDEX) {
+		Some(ext) => match BlockExtrinsic::<Set>::try_from(ext) {
+			Ok(x) => Some(x.call.now / 1000),
+			Err(err) => {
+				let error = std::format!("Failed convert raw Timestamp::Set to normal extrinsic. Reason: {}", err);
+				return Err(permanent(error, ext));
+			},
+		},
+		None => None,
 	};
-	let failed_tx = match BlockExtrinsic::<FailedSendMessageTxs>::try_from(failed_tx) {
-		Ok(x) => x,
-		Err(err) => {
-			return Err(std::format!(
-				"Failed convert raw Vector::FailedSendMessageTxs to normal extrinsic. Reason: {}",
-				err
-			));
+
+	let failed_txs = match find(FailedSendMessageTxs::HEADER_INDEX) {
+		Some(ext) => match BlockExtrinsic::<FailedSendMessageTxs>::try_from(ext) {
+			Ok(x) => Some(x.call.failed_txs),
+			Err(err) => {
+				let error = std::format!(
+					"Failed convert raw Vector::FailedSendMessageTxs to normal extrinsic. Reason: {}",
+					err
+				);
+				return Err(permanent(error, ext));
+			},
 		},
+		None => None,
 	};
 
-	Ok((set_tx.call.now / 1000, failed_tx.call.failed_txs))
+	Ok((timestamp, failed_txs))
 }
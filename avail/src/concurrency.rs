@@ -0,0 +1,207 @@
+//! AIMD controller for the number of fetch tasks.
+//!
+//! Every finished fetch is recorded together with how long it took. Once per window the controller looks at what
+//! was recorded: if too many fetches failed or timed out, or the latency per request grew well above the best
+//! latency seen so far, the node is struggling and the limit is halved. Otherwise, if the window saw enough
+//! traffic to tell, the limit grows by one. This keeps the indexer fast on a dedicated node without getting rate
+//! limited by a shared public one.
+
+use std::time::{Duration, Instant};
+
+/// Limit right after start. Grows from here if the node keeps up.
+const INITIAL_LIMIT: u32 = 4;
+/// How long outcomes are collected before the limit is adjusted
+const ADJUST_INTERVAL: Duration = Duration::from_secs(5);
+/// Share of failed or timed out fetches in a window above which the limit is decreased
+const MAX_ERROR_RATE: f64 = 0.1;
+/// The limit is decreased when the latency per request is this many times the baseline
+const LATENCY_TOLERANCE: f64 = 2.0;
+/// How fast the baseline follows a latency that is higher than it. Lets it recover from a lucky window.
+const BASELINE_DRIFT: f64 = 0.05;
+const MULTIPLICATIVE_DECREASE: f64 = 0.5;
+const ADDITIVE_INCREASE: f64 = 1.0;
+
+/// How a fetch ended, as far as the node is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+	/// The block was fetched with this many RPC requests
+	Success { request_count: u32 },
+	/// The node returned an error or the connection failed
+	Error,
+	/// The node did not answer in time
+	Timeout,
+}
+
+pub struct ConcurrencyController {
+	limit: f64,
+	max_limit: u32,
+	/// Lowest latency per request seen, slowly drifting towards the current one
+	baseline_latency: Option<Duration>,
+	window_start: Instant,
+	window: Window,
+	/// Average latency per request of the last finished window
+	last_latency: Option<Duration>,
+	/// Share of failed or timed out fetches of the last finished window
+	last_error_rate: f64,
+}
+
+#[derive(Default)]
+struct Window {
+	successes: u32,
+	errors: u32,
+	timeouts: u32,
+	request_count: u32,
+	latency: Duration,
+}
+
+impl ConcurrencyController {
+	pub fn new(max_limit: u32) -> Self {
+		let max_limit = max_limit.max(1);
+		Self {
+			limit: INITIAL_LIMIT.min(max_limit) as f64,
+			max_limit,
+			baseline_latency: None,
+			window_start: Instant::now(),
+			window: Window::default(),
+			last_latency: None,
+			last_error_rate: 0.0,
+		}
+	}
+
+	/// How many fetch tasks may run right now
+	pub fn limit(&self) -> u32 {
+		self.limit as u32
+	}
+
+	pub fn last_latency(&self) -> Option<Duration> {
+		self.last_latency
+	}
+
+	pub fn last_error_rate(&self) -> f64 {
+		self.last_error_rate
+	}
+
+	/// Records a finished fetch. `latency` is the time the whole fetch took.
+	pub fn record(&mut self, outcome: Outcome, latency: Duration) {
+		match outcome {
+			Outcome::Success { request_count } => {
+				self.window.successes += 1;
+				self.window.request_count += request_count.max(1);
+				self.window.latency += latency;
+			},
+			Outcome::Error => self.window.errors += 1,
+			Outcome::Timeout => self.window.timeouts += 1,
+		}
+
+		if self.window_start.elapsed() >= ADJUST_INTERVAL {
+			self.adjust();
+		}
+	}
+
+	fn adjust(&mut self) {
+		let window = std::mem::take(&mut self.window);
+		self.window_start = Instant::now();
+
+		let total = window.successes + window.errors + window.timeouts;
+		if total == 0 {
+			return;
+		}
+
+		self.last_error_rate = (window.errors + window.timeouts) as f64 / total as f64;
+		self.last_latency = (window.request_count > 0).then(|| window.latency / window.request_count);
+
+		let is_slow = match (self.last_latency, self.baseline_latency) {
+			(Some(latency), Some(baseline)) => latency.as_secs_f64() > baseline.as_secs_f64() * LATENCY_TOLERANCE,
+			_ => false,
+		};
+		if let Some(latency) = self.last_latency {
+			self.baseline_latency = Some(match self.baseline_latency {
+				Some(baseline) if baseline <= latency => baseline + (latency - baseline).mul_f64(BASELINE_DRIFT),
+				_ => latency,
+			});
+		}
+
+		if self.last_error_rate > MAX_ERROR_RATE || is_slow {
+			self.limit = (self.limit * MULTIPLICATIVE_DECREASE).max(1.0);
+			return;
+		}
+
+		// A window with fewer fetches than allowed tasks doesn't show whether the node can take more.
+		if total >= self.limit() {
+			self.limit = (self.limit + ADDITIVE_INCREASE).min(self.max_limit as f64);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Lets a window of fetches end and adjusts the limit right away
+	fn adjust(controller: &mut ConcurrencyController, successes: u32, errors: u32, latency_per_request: Duration) {
+		controller.window = Window {
+			successes,
+			errors,
+			timeouts: 0,
+			request_count: successes,
+			latency: latency_per_request * successes,
+		};
+		controller.adjust();
+	}
+
+	#[test]
+	fn errors_halve_the_limit() {
+		let mut controller = ConcurrencyController::new(16);
+		adjust(&mut controller, 4, 4, Duration::from_millis(100));
+		assert_eq!(controller.limit(), 2);
+		assert_eq!(controller.last_error_rate(), 0.5);
+
+		adjust(&mut controller, 0, 4, Duration::ZERO);
+		adjust(&mut controller, 0, 4, Duration::ZERO);
+		assert_eq!(controller.limit(), 1);
+	}
+
+	#[test]
+	fn busy_window_grows_the_limit_up_to_max() {
+		let mut controller = ConcurrencyController::new(6);
+		adjust(&mut controller, 4, 0, Duration::from_millis(100));
+		assert_eq!(controller.limit(), 5);
+		assert_eq!(controller.last_latency(), Some(Duration::from_millis(100)));
+
+		adjust(&mut controller, 5, 0, Duration::from_millis(100));
+		adjust(&mut controller, 6, 0, Duration::from_millis(100));
+		assert_eq!(controller.limit(), 6);
+	}
+
+	#[test]
+	fn quiet_window_keeps_the_limit() {
+		let mut controller = ConcurrencyController::new(16);
+		adjust(&mut controller, 3, 0, Duration::from_millis(100));
+		assert_eq!(controller.limit(), 4);
+
+		// Nothing was fetched, so nothing is known about the node.
+		controller.adjust();
+		assert_eq!(controller.limit(), 4);
+	}
+
+	#[test]
+	fn slow_window_halves_the_limit() {
+		let mut controller = ConcurrencyController::new(16);
+		adjust(&mut controller, 4, 0, Duration::from_millis(100));
+		assert_eq!(controller.limit(), 5);
+
+		adjust(&mut controller, 5, 0, Duration::from_millis(300));
+		assert_eq!(controller.limit(), 2);
+		assert_eq!(controller.last_error_rate(), 0.0);
+	}
+
+	#[test]
+	fn record_waits_for_the_window_to_end() {
+		let mut controller = ConcurrencyController::new(16);
+		for _ in 0..8 {
+			controller.record(Outcome::Error, Duration::from_millis(100));
+		}
+		assert_eq!(controller.limit(), 4);
+		assert_eq!(controller.last_latency(), None);
+	}
+}
@@ -4,11 +4,23 @@ use std::{env, num::ParseIntError};
 pub struct ConfigurationFile {
 	pub db_url: Option<String>,
 	pub avail_url: Option<String>,
+	pub avail_endpoints: Option<Vec<AvailEndpoint>>,
 	pub table_name: Option<String>,
 	pub send_message_table_name: Option<String>,
 	pub execute_table_name: Option<String>,
+	pub checkpoint_table_name: Option<String>,
+	pub event_table_name: Option<String>,
+	pub bridge_message_table_name: Option<String>,
+	pub block_table_name: Option<String>,
+	pub quarantine_table_name: Option<String>,
+	pub dead_letter_table_name: Option<String>,
 	pub block_height: Option<u32>,
 	pub max_task_count: Option<u32>,
+	pub rpc_connection_count: Option<u32>,
+	pub write_batch_size: Option<u32>,
+	pub write_flush_interval_ms: Option<u32>,
+	pub live_tail_threshold: Option<u32>,
+	pub tracked_calls: Option<Vec<TrackedCall>>,
 	pub observability: Option<Observability>,
 	pub log_interval_ms: Option<u32>,
 }
@@ -16,16 +28,54 @@ pub struct ConfigurationFile {
 #[derive(Debug, Clone)]
 pub struct Configuration {
 	pub db_url: String,
-	pub avail_url: String,
+	/// Nodes to fetch blocks from. Holds at least one endpoint.
+	pub avail_endpoints: Vec<AvailEndpoint>,
 	pub table_name: String,
 	pub send_message_table_name: String,
 	pub execute_table_name: String,
+	pub checkpoint_table_name: String,
+	pub event_table_name: String,
+	pub bridge_message_table_name: String,
+	pub block_table_name: String,
+	pub quarantine_table_name: String,
+	pub dead_letter_table_name: String,
 	pub block_height: Option<u32>,
 	pub max_task_count: u32,
+	/// Clients kept open to every endpoint. Shared by all tasks.
+	pub rpc_connection_count: u32,
+	pub write_batch_size: u32,
+	pub write_flush_interval_ms: u32,
+	pub live_tail_threshold: u32,
+	/// Calls indexed in addition to the built-in Vector calls
+	pub tracked_calls: Vec<TrackedCall>,
 	pub observability: Observability,
 	pub log_interval_ms: u32,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub struct AvailEndpoint {
+	pub url: String,
+	/// Share of the fetch tasks this endpoint gets relative to the others
+	#[serde(default = "default_endpoint_weight")]
+	pub weight: u32,
+}
+
+fn default_endpoint_weight() -> u32 {
+	1
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct TrackedCall {
+	/// Used in logs and dead letter errors
+	pub name: String,
+	pub pallet_id: u8,
+	pub variant_id: u8,
+	/// One of `send_message`, `execute`, `vector_fulfill` or `raw`
+	pub decoder: String,
+	/// Table the `raw` decoder writes to
+	pub table_name: Option<String>,
+}
+
 #[derive(Debug, Clone, serde::Deserialize, Default)]
 pub struct Observability {
 	pub traces_endpoint: Option<String>,  // If None then no traces will be send
@@ -75,6 +125,24 @@ impl Configuration {
 		};
 		println!("AVAIL_URL: {}", avail_url);
 
+		let avail_endpoints = if let Ok(value) = env::var("AVAIL_ENDPOINTS") {
+			println!("AVAIL_ENDPOINTS: ENV");
+			parse_avail_endpoints(&value)
+		} else if let Some(value) = config_file.avail_endpoints {
+			println!("AVAIL_ENDPOINTS: FILE");
+			value
+		} else {
+			println!("AVAIL_ENDPOINTS: AVAIL_URL");
+			vec![AvailEndpoint { url: avail_url, weight: 1 }]
+		};
+		if avail_endpoints.is_empty() {
+			return Err("AVAIL_ENDPOINTS must contain at least one endpoint.".into());
+		}
+		if avail_endpoints.iter().any(|x| x.weight == 0) {
+			return Err("AVAIL_ENDPOINTS weights must be greater than 0.".into());
+		}
+		println!("AVAIL_ENDPOINTS: {:?}", avail_endpoints);
+
 		let block_height: Option<u32> = if let Ok(value) = env::var("BLOCK_HEIGHT") {
 			println!("BLOCK_HEIGHT: ENV");
 			Some(
@@ -129,6 +197,78 @@ impl Configuration {
 		};
 		println!("EXECUTE_TABLE_NAME: {:?}", execute_table_name);
 
+		let checkpoint_table_name = if let Ok(value) = env::var("CHECKPOINT_TABLE_NAME") {
+			println!("CHECKPOINT_TABLE_NAME: ENV");
+			value
+		} else if let Some(value) = config_file.checkpoint_table_name {
+			println!("CHECKPOINT_TABLE_NAME: FILE");
+			value
+		} else {
+			println!("CHECKPOINT_TABLE_NAME: DEFAULT");
+			String::from("avail_checkpoint_table")
+		};
+		println!("CHECKPOINT_TABLE_NAME: {:?}", checkpoint_table_name);
+
+		let event_table_name = if let Ok(value) = env::var("EVENT_TABLE_NAME") {
+			println!("EVENT_TABLE_NAME: ENV");
+			value
+		} else if let Some(value) = config_file.event_table_name {
+			println!("EVENT_TABLE_NAME: FILE");
+			value
+		} else {
+			println!("EVENT_TABLE_NAME: DEFAULT");
+			String::from("avail_event_table")
+		};
+		println!("EVENT_TABLE_NAME: {:?}", event_table_name);
+
+		let bridge_message_table_name = if let Ok(value) = env::var("BRIDGE_MESSAGE_TABLE_NAME") {
+			println!("BRIDGE_MESSAGE_TABLE_NAME: ENV");
+			value
+		} else if let Some(value) = config_file.bridge_message_table_name {
+			println!("BRIDGE_MESSAGE_TABLE_NAME: FILE");
+			value
+		} else {
+			println!("BRIDGE_MESSAGE_TABLE_NAME: DEFAULT");
+			String::from("avail_bridge_message_table")
+		};
+		println!("BRIDGE_MESSAGE_TABLE_NAME: {:?}", bridge_message_table_name);
+
+		let block_table_name = if let Ok(value) = env::var("BLOCK_TABLE_NAME") {
+			println!("BLOCK_TABLE_NAME: ENV");
+			value
+		} else if let Some(value) = config_file.block_table_name {
+			println!("BLOCK_TABLE_NAME: FILE");
+			value
+		} else {
+			println!("BLOCK_TABLE_NAME: DEFAULT");
+			String::from("avail_block_table")
+		};
+		println!("BLOCK_TABLE_NAME: {:?}", block_table_name);
+
+		let quarantine_table_name = if let Ok(value) = env::var("QUARANTINE_TABLE_NAME") {
+			println!("QUARANTINE_TABLE_NAME: ENV");
+			value
+		} else if let Some(value) = config_file.quarantine_table_name {
+			println!("QUARANTINE_TABLE_NAME: FILE");
+			value
+		} else {
+			println!("QUARANTINE_TABLE_NAME: DEFAULT");
+			String::from("avail_quarantine_table")
+		};
+		println!("QUARANTINE_TABLE_NAME: {:?}", quarantine_table_name);
+
+		let dead_letter_table_name = if let Ok(value) = env::var("DEAD_LETTER_TABLE_NAME") {
+			println!("DEAD_LETTER_TABLE_NAME: ENV");
+			value
+		} else if let Some(value) = config_file.dead_letter_table_name {
+			println!("DEAD_LETTER_TABLE_NAME: FILE");
+			value
+		} else {
+			println!("DEAD_LETTER_TABLE_NAME: DEFAULT");
+			String::from("avail_dead_letter_table")
+		};
+		println!("DEAD_LETTER_TABLE_NAME: {:?}", dead_letter_table_name);
+
 		let max_task_count: u32 = if let Ok(value) = env::var("MAX_TASK_COUNT") {
 			println!("MAX_TASK_COUNT: ENV");
 			value.parse().map_err(|e: ParseIntError| e.to_string())?
@@ -141,6 +281,66 @@ impl Configuration {
 		};
 		println!("MAX_TASK_COUNT: {:?}", max_task_count);
 
+		let rpc_connection_count: u32 = if let Ok(value) = env::var("RPC_CONNECTION_COUNT") {
+			println!("RPC_CONNECTION_COUNT: ENV");
+			value.parse().map_err(|e: ParseIntError| e.to_string())?
+		} else if let Some(value) = config_file.rpc_connection_count {
+			println!("RPC_CONNECTION_COUNT: FILE");
+			value
+		} else {
+			println!("RPC_CONNECTION_COUNT: DEFAULT");
+			4
+		};
+		println!("RPC_CONNECTION_COUNT: {:?}", rpc_connection_count);
+
+		let write_batch_size: u32 = if let Ok(value) = env::var("WRITE_BATCH_SIZE") {
+			println!("WRITE_BATCH_SIZE: ENV");
+			value.parse().map_err(|e: ParseIntError| e.to_string())?
+		} else if let Some(value) = config_file.write_batch_size {
+			println!("WRITE_BATCH_SIZE: FILE");
+			value
+		} else {
+			println!("WRITE_BATCH_SIZE: DEFAULT");
+			100
+		};
+		println!("WRITE_BATCH_SIZE: {:?}", write_batch_size);
+
+		let write_flush_interval_ms: u32 = if let Ok(value) = env::var("WRITE_FLUSH_INTERVAL_MS") {
+			println!("WRITE_FLUSH_INTERVAL_MS: ENV");
+			value.parse().map_err(|e: ParseIntError| e.to_string())?
+		} else if let Some(value) = config_file.write_flush_interval_ms {
+			println!("WRITE_FLUSH_INTERVAL_MS: FILE");
+			value
+		} else {
+			println!("WRITE_FLUSH_INTERVAL_MS: DEFAULT");
+			5_000
+		};
+		println!("WRITE_FLUSH_INTERVAL_MS: {:?}", write_flush_interval_ms);
+
+		let live_tail_threshold: u32 = if let Ok(value) = env::var("LIVE_TAIL_THRESHOLD") {
+			println!("LIVE_TAIL_THRESHOLD: ENV");
+			value.parse().map_err(|e: ParseIntError| e.to_string())?
+		} else if let Some(value) = config_file.live_tail_threshold {
+			println!("LIVE_TAIL_THRESHOLD: FILE");
+			value
+		} else {
+			println!("LIVE_TAIL_THRESHOLD: DEFAULT");
+			1_000
+		};
+		println!("LIVE_TAIL_THRESHOLD: {:?}", live_tail_threshold);
+
+		let tracked_calls: Vec<TrackedCall> = if let Ok(value) = env::var("TRACKED_CALLS") {
+			println!("TRACKED_CALLS: ENV");
+			serde_json::from_str(&value).map_err(|e| std::format!("Failed to parse TRACKED_CALLS as JSON. {}", e))?
+		} else if let Some(value) = config_file.tracked_calls {
+			println!("TRACKED_CALLS: FILE");
+			value
+		} else {
+			println!("TRACKED_CALLS: DEFAULT");
+			Vec::new()
+		};
+		println!("TRACKED_CALLS: {:?}", tracked_calls);
+
 		let mut observability = config_file.observability.unwrap_or_default();
 		if let Ok(endpoint) = env::var("TRACES_ENDPOINT") {
 			observability.traces_endpoint = Some(endpoint);
@@ -175,14 +375,68 @@ impl Configuration {
 
 		Ok(Configuration {
 			db_url,
-			avail_url,
+			avail_endpoints,
 			table_name,
 			block_height,
 			send_message_table_name,
 			execute_table_name,
+			checkpoint_table_name,
+			event_table_name,
+			bridge_message_table_name,
+			block_table_name,
+			quarantine_table_name,
+			dead_letter_table_name,
 			max_task_count,
+			rpc_connection_count,
+			write_batch_size,
+			write_flush_interval_ms,
+			live_tail_threshold,
+			tracked_calls,
 			observability,
 			log_interval_ms,
 		})
 	}
 }
+
+/// Comma separated list of `url` or `url=weight` entries
+fn parse_avail_endpoints(value: &str) -> Vec<AvailEndpoint> {
+	value
+		.split(',')
+		.map(|x| x.trim())
+		.filter(|x| !x.is_empty())
+		.map(|entry| match entry.rsplit_once('=').map(|(url, weight)| (url, weight.parse::<u32>())) {
+			Some((url, Ok(weight))) => AvailEndpoint { url: url.to_string(), weight },
+			_ => AvailEndpoint { url: entry.to_string(), weight: 1 },
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn endpoint(url: &str, weight: u32) -> AvailEndpoint {
+		AvailEndpoint { url: url.to_string(), weight }
+	}
+
+	#[test]
+	fn parse_avail_endpoints_reads_weights() {
+		let endpoints = parse_avail_endpoints("wss://a.avail.so/ws=3, wss://b.avail.so/ws");
+		assert_eq!(endpoints, vec![endpoint("wss://a.avail.so/ws", 3), endpoint("wss://b.avail.so/ws", 1)]);
+	}
+
+	#[test]
+	fn parse_avail_endpoints_keeps_urls_with_equal_signs() {
+		let endpoints = parse_avail_endpoints("wss://a.avail.so/ws?key=abc");
+		assert_eq!(endpoints, vec![endpoint("wss://a.avail.so/ws?key=abc", 1)]);
+
+		let endpoints = parse_avail_endpoints("wss://a.avail.so/ws?key=abc=2");
+		assert_eq!(endpoints, vec![endpoint("wss://a.avail.so/ws?key=abc", 2)]);
+	}
+
+	#[test]
+	fn parse_avail_endpoints_skips_empty_entries() {
+		assert!(parse_avail_endpoints("").is_empty());
+		assert_eq!(parse_avail_endpoints(" ,wss://a.avail.so/ws,"), vec![endpoint("wss://a.avail.so/ws", 1)]);
+	}
+}
@@ -0,0 +1,106 @@
+use crate::db::{Database, types};
+use avail_rust::H256;
+use sqlx::{
+	Postgres, Transaction,
+	types::chrono::{DateTime, Utc},
+};
+
+pub struct BlockTable;
+impl BlockTable {
+	pub async fn find_range(from: u32, to: u32, db: &Database) -> Result<Vec<(u32, H256)>, String> {
+		let q = std::format!(
+			"SELECT block_height, block_hash FROM {} WHERE block_height BETWEEN $1 AND $2",
+			db.block_table_name
+		);
+		let rows = sqlx::query(&q)
+			.bind(types::encode_u32(from))
+			.bind(types::encode_u32(to))
+			.fetch_all(&db.conn)
+			.await
+			.map_err(|e| e.to_string())?;
+
+		let mut result = Vec::with_capacity(rows.len());
+		for row in rows {
+			result.push((types::read_u32(&row, "block_height")?, types::read_h256(&row, "block_hash")?));
+		}
+
+		Ok(result)
+	}
+
+	/// Upserts all entries with a single statement.
+	pub async fn insert_many(
+		values: &[TableEntry],
+		db: &Database,
+		tx: &mut Transaction<'_, Postgres>,
+	) -> Result<(), String> {
+		if values.is_empty() {
+			return Ok(());
+		}
+
+		let q = std::format!(
+			"
+				INSERT INTO {} (
+					block_height,
+					block_hash,
+					parent_hash,
+					block_timestamp,
+					tracked_ext_count
+				)
+				SELECT * FROM UNNEST(
+					$1::BIGINT[],
+					$2::BYTEA[],
+					$3::BYTEA[],
+					$4::TIMESTAMPTZ[],
+					$5::BIGINT[]
+				)
+				ON CONFLICT (block_height) DO UPDATE SET
+					block_hash = EXCLUDED.block_hash,
+					parent_hash = EXCLUDED.parent_hash,
+					block_timestamp = EXCLUDED.block_timestamp,
+					tracked_ext_count = EXCLUDED.tracked_ext_count
+			",
+			db.block_table_name
+		);
+
+		let mut block_heights = Vec::with_capacity(values.len());
+		let mut block_hashes = Vec::with_capacity(values.len());
+		let mut parent_hashes = Vec::with_capacity(values.len());
+		let mut block_timestamps = Vec::with_capacity(values.len());
+		let mut tracked_ext_counts = Vec::with_capacity(values.len());
+		for value in values {
+			let block_timestamp = DateTime::<Utc>::from_timestamp(value.block_timestamp as i64, 0)
+				.ok_or_else(|| "Failed to convert block_timestamp to chrono DateTime".to_string())?;
+			block_heights.push(types::encode_u32(value.block_height));
+			block_hashes.push(types::encode_h256(&value.block_hash));
+			parent_hashes.push(types::encode_h256(&value.parent_hash));
+			block_timestamps.push(block_timestamp);
+			tracked_ext_counts.push(types::encode_u32(value.tracked_ext_count));
+		}
+
+		let _ = sqlx::query(&q)
+			.bind(block_heights)
+			.bind(block_hashes)
+			.bind(parent_hashes)
+			.bind(block_timestamps)
+			.bind(tracked_ext_counts)
+			.execute(&mut **tx)
+			.await
+			.map_err(|e| e.to_string())?;
+
+		Ok(())
+	}
+}
+
+pub struct TableEntry {
+	/// In the DB this is stored as "BIGINT PRIMARY KEY"
+	pub block_height: u32,
+	/// In the DB this is stored as "BYTEA NOT NULL"
+	pub block_hash: H256,
+	/// In the DB this is stored as "BYTEA NOT NULL"
+	pub parent_hash: H256,
+	/// In the DB this is stored as "TIMESTAMPTZ NOT NULL"
+	pub block_timestamp: u64,
+	/// Number of main table rows stored for the block
+	/// In the DB this is stored as "BIGINT NOT NULL"
+	pub tracked_ext_count: u32,
+}
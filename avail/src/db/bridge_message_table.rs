@@ -0,0 +1,283 @@
+use sqlx::{Postgres, Transaction};
+use std::collections::BTreeMap;
+
+use crate::db::{Database, types};
+
+/// Statuses in the order in which a message goes through them. Same vocabulary as `ethereum/src/types.ts`.
+/// A status is only ever replaced by one that comes later in this list.
+const STATUS_ORDER: &str = "ARRAY['initiated', 'in_progress', 'claim_ready', 'bridged']::TEXT[]";
+
+pub struct BridgeMessageTable;
+impl BridgeMessageTable {
+	/// Upserts all entries with a single statement. Statuses never move backwards.
+	/// Entries with the same key are merged first, as one statement can't update a row twice.
+	pub async fn insert_many(
+		values: &[TableEntry],
+		db: &Database,
+		tx: &mut Transaction<'_, Postgres>,
+	) -> Result<(), String> {
+		if values.is_empty() {
+			return Ok(());
+		}
+
+		let q = std::format!(
+			"
+				INSERT INTO {} AS t (
+					direction,
+					message_id,
+					status,
+					slot,
+					send_ext_id,
+					execute_ext_id,
+					updated_at
+				)
+				SELECT direction, message_id, status, slot, send_ext_id, execute_ext_id, NOW() FROM UNNEST(
+					$1::TEXT[],
+					$2::TEXT[]::NUMERIC(20)[],
+					$3::TEXT[],
+					$4::TEXT[]::NUMERIC(20)[],
+					$5::TEXT[]::NUMERIC(20)[],
+					$6::TEXT[]::NUMERIC(20)[]
+				) AS u(direction, message_id, status, slot, send_ext_id, execute_ext_id)
+				ON CONFLICT (direction, message_id) DO UPDATE SET
+					status = CASE
+						WHEN array_position({}, EXCLUDED.status) > array_position({}, t.status) THEN EXCLUDED.status
+						ELSE t.status
+					END,
+					slot = COALESCE(EXCLUDED.slot, t.slot),
+					send_ext_id = COALESCE(EXCLUDED.send_ext_id, t.send_ext_id),
+					execute_ext_id = COALESCE(EXCLUDED.execute_ext_id, t.execute_ext_id),
+					updated_at = EXCLUDED.updated_at
+			",
+			&db.bridge_message_table_name,
+			STATUS_ORDER,
+			STATUS_ORDER
+		);
+
+		let values = merge(values);
+		let mut directions = Vec::with_capacity(values.len());
+		let mut message_ids = Vec::with_capacity(values.len());
+		let mut statuses = Vec::with_capacity(values.len());
+		let mut slots = Vec::with_capacity(values.len());
+		let mut send_ext_ids = Vec::with_capacity(values.len());
+		let mut execute_ext_ids = Vec::with_capacity(values.len());
+		for value in &values {
+			directions.push(value.direction.as_str());
+			message_ids.push(types::encode_u64(value.message_id));
+			statuses.push(value.status.as_str());
+			slots.push(value.slot.map(types::encode_u64));
+			send_ext_ids.push(value.send_ext_id.map(types::encode_u64));
+			execute_ext_ids.push(value.execute_ext_id.map(types::encode_u64));
+		}
+
+		let _ = sqlx::query(&q)
+			.bind(directions)
+			.bind(message_ids)
+			.bind(statuses)
+			.bind(slots)
+			.bind(send_ext_ids)
+			.bind(execute_ext_ids)
+			.execute(&mut **tx)
+			.await
+			.map_err(|e| e.to_string())?;
+
+		Ok(())
+	}
+
+	/// Moves Ethereum to Avail messages sent at or before the new slot to `claim_ready`, as they can be proven
+	/// against it now. A Vector head update says nothing about Avail to Ethereum messages: those are relayed through
+	/// the merkle roots posted to Ethereum and are advanced by the Ethereum indexer.
+	pub async fn advance_on_head_update(
+		head: VectorHead,
+		db: &Database,
+		tx: &mut Transaction<'_, Postgres>,
+	) -> Result<(), String> {
+		let q = std::format!(
+			"
+				UPDATE {} SET
+					status = $1,
+					updated_at = NOW()
+				WHERE direction = $2 AND status IN ($3, $4) AND slot <= $5::TEXT::NUMERIC(20)
+			",
+			&db.bridge_message_table_name
+		);
+
+		let _ = sqlx::query(&q)
+			.bind(Status::ClaimReady.as_str())
+			.bind(Direction::EthereumToAvail.as_str())
+			.bind(Status::Initiated.as_str())
+			.bind(Status::InProgress.as_str())
+			.bind(types::encode_u64(head.slot))
+			.execute(&mut **tx)
+			.await
+			.map_err(|e| e.to_string())?;
+
+		Ok(())
+	}
+}
+
+/// Latest Vector head update of a block or batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct VectorHead {
+	/// Main table id of the `Vector::fulfill` extrinsic. Compared first, so the later update wins.
+	pub ext_id: u64,
+	/// Ethereum slot of the new head
+	pub slot: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableEntry {
+	/// In the DB this is stored as "TEXT NOT NULL" and together with message_id forms the primary key
+	pub direction: Direction,
+	/// In the DB this is stored as "NUMERIC(20) NOT NULL"
+	pub message_id: u64,
+	/// In the DB this is stored as "TEXT NOT NULL"
+	pub status: Status,
+	/// Ethereum slot against which an Ethereum to Avail message was proven
+	/// In the DB this is stored as "NUMERIC(20)"
+	pub slot: Option<u64>,
+	/// Main table id of the Avail Send Message extrinsic
+	/// In the DB this is stored as "NUMERIC(20)"
+	pub send_ext_id: Option<u64>,
+	/// Main table id of the Avail Execute extrinsic
+	/// In the DB this is stored as "NUMERIC(20)"
+	pub execute_ext_id: Option<u64>,
+}
+
+impl TableEntry {
+	/// Observed an Avail Send Message
+	pub fn initiated(message_id: u64, send_ext_id: u64) -> Self {
+		Self {
+			direction: Direction::AvailToEthereum,
+			message_id,
+			status: Status::Initiated,
+			slot: None,
+			send_ext_id: Some(send_ext_id),
+			execute_ext_id: None,
+		}
+	}
+
+	/// Observed an Avail Execute that was proven against `slot` but did not succeed, or whose outcome is unknown.
+	/// The message can still be claimed.
+	pub fn claim_ready(message_id: u64, slot: u64) -> Self {
+		Self {
+			direction: Direction::EthereumToAvail,
+			message_id,
+			status: Status::ClaimReady,
+			slot: Some(slot),
+			send_ext_id: None,
+			execute_ext_id: None,
+		}
+	}
+
+	/// Observed a successful Avail Execute
+	pub fn bridged(message_id: u64, slot: u64, execute_ext_id: u64) -> Self {
+		Self {
+			direction: Direction::EthereumToAvail,
+			message_id,
+			status: Status::Bridged,
+			slot: Some(slot),
+			send_ext_id: None,
+			execute_ext_id: Some(execute_ext_id),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+	AvailToEthereum,
+	EthereumToAvail,
+}
+
+impl Direction {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Direction::AvailToEthereum => "avail_to_ethereum",
+			Direction::EthereumToAvail => "ethereum_to_avail",
+		}
+	}
+}
+
+/// Same vocabulary as `STATUS` in `ethereum/src/types.ts`. Ordered the same way as `STATUS_ORDER`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+	Initiated,
+	InProgress,
+	ClaimReady,
+	Bridged,
+}
+
+impl Status {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Status::Initiated => "initiated",
+			Status::InProgress => "in_progress",
+			Status::ClaimReady => "claim_ready",
+			Status::Bridged => "bridged",
+		}
+	}
+}
+
+/// One entry per key, merged the same way the upsert merges an entry into an existing row: the status that comes
+/// last in `STATUS_ORDER` wins and later values replace earlier ones.
+fn merge(values: &[TableEntry]) -> Vec<TableEntry> {
+	let mut merged: BTreeMap<(&'static str, u64), TableEntry> = BTreeMap::new();
+	for value in values {
+		let key = (value.direction.as_str(), value.message_id);
+		let Some(entry) = merged.get_mut(&key) else {
+			merged.insert(key, value.clone());
+			continue;
+		};
+
+		entry.status = entry.status.max(value.status);
+		entry.slot = value.slot.or(entry.slot);
+		entry.send_ext_id = value.send_ext_id.or(entry.send_ext_id);
+		entry.execute_ext_id = value.execute_ext_id.or(entry.execute_ext_id);
+	}
+
+	merged.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn merge_keeps_successful_execute_of_batch() {
+		let failed = TableEntry::claim_ready(7, 100);
+		let succeeded = TableEntry::bridged(7, 100, 42);
+		let expected = vec![TableEntry::bridged(7, 100, 42)];
+
+		assert_eq!(merge(&[failed.clone(), succeeded.clone()]), expected);
+		assert_eq!(merge(&[succeeded, failed]), expected);
+	}
+
+	#[test]
+	fn merge_collapses_repeated_failed_executes() {
+		let values = [TableEntry::claim_ready(7, 100), TableEntry::claim_ready(7, 101)];
+		assert_eq!(merge(&values), vec![TableEntry::claim_ready(7, 101)]);
+	}
+
+	#[test]
+	fn merge_keeps_directions_and_messages_apart() {
+		let values = [
+			TableEntry::initiated(7, 1),
+			TableEntry::claim_ready(7, 100),
+			TableEntry::claim_ready(8, 100),
+		];
+		assert_eq!(merge(&values).len(), 3);
+	}
+
+	#[test]
+	fn status_order_matches_sql() {
+		let statuses = [
+			Status::Initiated,
+			Status::InProgress,
+			Status::ClaimReady,
+			Status::Bridged,
+		];
+		let names: Vec<String> = statuses.iter().map(|x| std::format!("'{}'", x.as_str())).collect();
+		assert_eq!(STATUS_ORDER, std::format!("ARRAY[{}]::TEXT[]", names.join(", ")));
+		assert!(statuses.windows(2).all(|x| x[0] < x[1]));
+	}
+}
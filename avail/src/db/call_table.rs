@@ -0,0 +1,119 @@
+use crate::db::{Database, types};
+use sqlx::{Postgres, Transaction};
+use std::collections::BTreeMap;
+
+/// Tables of calls that are tracked through configuration with the raw decoder. They depend on configuration
+/// rather than on the schema version, so they are created on startup instead of by a migration.
+pub struct CallTable;
+impl CallTable {
+	pub async fn create(table_name: &str, db: &Database) -> Result<(), String> {
+		let statements = [
+			std::format!(
+				"
+					CREATE TABLE IF NOT EXISTS {} (
+						id NUMERIC(20) PRIMARY KEY REFERENCES {},
+						block_height BIGINT NOT NULL,
+						pallet_id SMALLINT NOT NULL,
+						variant_id SMALLINT NOT NULL,
+						call BYTEA NOT NULL
+					);
+				",
+				table_name,
+				db.main_table_name
+			),
+			std::format!(
+				"CREATE INDEX IF NOT EXISTS {}_block_height_idx ON {} (block_height);",
+				table_name,
+				table_name
+			),
+		];
+
+		for statement in statements {
+			let _ = sqlx::query(&statement)
+				.execute(&db.conn)
+				.await
+				.map_err(|e| std::format!("Failed to create call table {}. Error: {}", table_name, e.to_string()))?;
+		}
+
+		Ok(())
+	}
+
+	/// Upserts all entries with one statement per table.
+	pub async fn insert_many(
+		values: &[TableEntry],
+		db: &Database,
+		tx: &mut Transaction<'_, Postgres>,
+	) -> Result<(), String> {
+		let mut tables: BTreeMap<&str, Vec<&TableEntry>> = BTreeMap::new();
+		for value in values {
+			tables.entry(value.table_name.as_str()).or_default().push(value);
+		}
+
+		for (table_name, values) in tables {
+			let q = std::format!(
+				"
+					INSERT INTO {} (
+						id,
+						block_height,
+						pallet_id,
+						variant_id,
+						call
+					)
+					SELECT * FROM UNNEST(
+						$1::TEXT[]::NUMERIC(20)[],
+						$2::BIGINT[],
+						$3::SMALLINT[],
+						$4::SMALLINT[],
+						$5::BYTEA[]
+					)
+					ON CONFLICT (id) DO UPDATE SET
+						block_height = EXCLUDED.block_height,
+						pallet_id = EXCLUDED.pallet_id,
+						variant_id = EXCLUDED.variant_id,
+						call = EXCLUDED.call
+				",
+				table_name
+			);
+
+			let mut ids = Vec::with_capacity(values.len());
+			let mut block_heights = Vec::with_capacity(values.len());
+			let mut pallet_ids = Vec::with_capacity(values.len());
+			let mut variant_ids = Vec::with_capacity(values.len());
+			let mut calls = Vec::with_capacity(values.len());
+			for value in values {
+				ids.push(types::encode_u64(value.id));
+				block_heights.push(types::encode_u32(value.block_height));
+				pallet_ids.push(value.pallet_id as i16);
+				variant_ids.push(value.variant_id as i16);
+				calls.push(value.call.clone());
+			}
+
+			let _ = sqlx::query(&q)
+				.bind(ids)
+				.bind(block_heights)
+				.bind(pallet_ids)
+				.bind(variant_ids)
+				.bind(calls)
+				.execute(&mut **tx)
+				.await
+				.map_err(|e| e.to_string())?;
+		}
+
+		Ok(())
+	}
+}
+
+pub struct TableEntry {
+	/// Table the entry is written to
+	pub table_name: String,
+	/// In the DB this is stored as "NUMERIC(20) PRIMARY KEY REFERENCES main_table"
+	pub id: u64,
+	/// In the DB this is stored as "BIGINT NOT NULL"
+	pub block_height: u32,
+	/// In the DB this is stored as "SMALLINT NOT NULL"
+	pub pallet_id: u8,
+	/// In the DB this is stored as "SMALLINT NOT NULL"
+	pub variant_id: u8,
+	/// In the DB this is stored as "BYTEA NOT NULL". SCALE encoded call.
+	pub call: Vec<u8>,
+}
@@ -0,0 +1,150 @@
+use crate::db::{Database, types};
+use avail_rust::H256;
+use sqlx::{
+	Postgres, Row, Transaction,
+	types::chrono::{DateTime, Utc},
+};
+
+/// Name of the checkpoint row that the live indexer reads and advances.
+pub const INDEXER_CHECKPOINT: &str = "indexer";
+
+const BACKFILL_CHECKPOINT_PREFIX: &str = "backfill_";
+
+/// Name of the checkpoint row of a bounded backfill. Every range has its own row.
+pub fn backfill_checkpoint(from: u32, to: u32) -> String {
+	std::format!("{}{}_{}", BACKFILL_CHECKPOINT_PREFIX, from, to)
+}
+
+/// Range of a backfill checkpoint name. None for any other checkpoint.
+pub fn parse_backfill_checkpoint(name: &str) -> Option<(u32, u32)> {
+	let (from, to) = name.strip_prefix(BACKFILL_CHECKPOINT_PREFIX)?.split_once('_')?;
+	Some((from.parse().ok()?, to.parse().ok()?))
+}
+
+pub struct CheckpointTable;
+impl CheckpointTable {
+	pub async fn find(name: &str, db: &Database) -> Result<Option<TableEntry>, String> {
+		let q = std::format!(
+			"SELECT block_height, block_hash, updated_at FROM {} WHERE name = $1",
+			db.checkpoint_table_name
+		);
+		let row = sqlx::query(&q)
+			.bind(name)
+			.fetch_optional(&db.conn)
+			.await
+			.map_err(|e| e.to_string())?;
+
+		let Some(row) = row else {
+			return Ok(None);
+		};
+
+		let block_height = types::read_u32(&row, "block_height")?;
+		let block_hash = types::read_h256(&row, "block_hash")?;
+		let updated_at = row
+			.try_get::<DateTime<Utc>, _>("updated_at")
+			.map_err(|e| std::format!("Failed to convert updated_at. Error: {}", e.to_string()))?;
+
+		Ok(Some(TableEntry { name: name.to_string(), block_height, block_hash, updated_at }))
+	}
+
+	/// Ranges of the backfills whose checkpoint has not reached the end of the range yet
+	pub async fn find_unfinished_backfills(db: &Database) -> Result<Vec<(u32, u32)>, String> {
+		let q = std::format!("SELECT name, block_height FROM {} WHERE starts_with(name, $1)", db.checkpoint_table_name);
+		let rows = sqlx::query(&q)
+			.bind(BACKFILL_CHECKPOINT_PREFIX)
+			.fetch_all(&db.conn)
+			.await
+			.map_err(|e| e.to_string())?;
+
+		let mut result = Vec::new();
+		for row in rows {
+			let name = row
+				.try_get::<String, _>("name")
+				.map_err(|e| std::format!("Failed to convert name. Error: {}", e.to_string()))?;
+			let block_height = types::read_u32(&row, "block_height")?;
+			let Some((from, to)) = parse_backfill_checkpoint(&name) else {
+				continue;
+			};
+			if block_height < to {
+				result.push((from, to));
+			}
+		}
+
+		Ok(result)
+	}
+
+	/// Never moves a checkpoint backwards. A backfill range can be worked on by a `backfill` command and by a
+	/// `run` that resumed it at the same time, and the one that is behind must not undo the progress of the other.
+	pub async fn upsert(value: TableEntry, db: &Database, tx: &mut Transaction<'_, Postgres>) -> Result<(), String> {
+		let q = std::format!(
+			"
+				INSERT INTO {} AS t (
+					name,
+					block_height,
+					block_hash,
+					updated_at
+				)
+				VALUES ($1, $2, $3, $4)
+				ON CONFLICT (name) DO UPDATE SET
+					block_height = EXCLUDED.block_height,
+					block_hash = EXCLUDED.block_hash,
+					updated_at = EXCLUDED.updated_at
+				WHERE t.block_height <= EXCLUDED.block_height
+			",
+			db.checkpoint_table_name
+		);
+		let _ = sqlx::query(&q)
+			.bind(value.name)
+			.bind(types::encode_u32(value.block_height))
+			.bind(types::encode_h256(&value.block_hash))
+			.bind(value.updated_at)
+			.execute(&mut **tx)
+			.await
+			.map_err(|e| e.to_string())?;
+
+		Ok(())
+	}
+}
+
+pub struct TableEntry {
+	/// In the DB this is stored as "TEXT PRIMARY KEY"
+	pub name: String,
+	/// In the DB this is stored as "BIGINT NOT NULL"
+	pub block_height: u32,
+	/// In the DB this is stored as "BYTEA NOT NULL"
+	pub block_hash: H256,
+	/// In the DB this is stored as "TIMESTAMPTZ NOT NULL"
+	pub updated_at: DateTime<Utc>,
+}
+
+impl TableEntry {
+	pub fn new(name: &str, block_height: u32, block_hash: H256) -> Self {
+		Self {
+			name: name.to_string(),
+			block_height,
+			block_hash,
+			updated_at: Utc::now(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn backfill_checkpoint_round_trip() {
+		for (from, to) in [(0, 0), (1, 1903463), (u32::MAX, u32::MAX)] {
+			assert_eq!(parse_backfill_checkpoint(&backfill_checkpoint(from, to)), Some((from, to)));
+		}
+	}
+
+	#[test]
+	fn parse_backfill_checkpoint_ignores_other_names() {
+		assert_eq!(parse_backfill_checkpoint(INDEXER_CHECKPOINT), None);
+		assert_eq!(parse_backfill_checkpoint("backfill_10"), None);
+		assert_eq!(parse_backfill_checkpoint("backfill_10_x"), None);
+		assert_eq!(parse_backfill_checkpoint("backfill_-1_10"), None);
+		assert_eq!(parse_backfill_checkpoint("other_10_20"), None);
+	}
+}
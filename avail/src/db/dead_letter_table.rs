@@ -0,0 +1,145 @@
+use crate::db::{Database, types};
+use avail_rust::{H256, block::BlockEncodedExtrinsic};
+use sqlx::{
+	Postgres, Transaction,
+	types::chrono::{DateTime, Utc},
+};
+
+pub struct DeadLetterTable;
+impl DeadLetterTable {
+	/// Upserts all entries with a single statement.
+	pub async fn insert_many(
+		values: &[TableEntry],
+		db: &Database,
+		tx: &mut Transaction<'_, Postgres>,
+	) -> Result<(), String> {
+		if values.is_empty() {
+			return Ok(());
+		}
+
+		let q = std::format!(
+			"
+				INSERT INTO {} (
+					block_height,
+					ext_index,
+					block_hash,
+					pallet_id,
+					variant_id,
+					call,
+					error,
+					created_at
+				)
+				SELECT * FROM UNNEST(
+					$1::BIGINT[],
+					$2::BIGINT[],
+					$3::BYTEA[],
+					$4::SMALLINT[],
+					$5::SMALLINT[],
+					$6::BYTEA[],
+					$7::TEXT[],
+					$8::TIMESTAMPTZ[]
+				)
+				ON CONFLICT (block_height, ext_index) DO UPDATE SET
+					block_hash = EXCLUDED.block_hash,
+					pallet_id = EXCLUDED.pallet_id,
+					variant_id = EXCLUDED.variant_id,
+					call = EXCLUDED.call,
+					error = EXCLUDED.error,
+					created_at = EXCLUDED.created_at
+			",
+			db.dead_letter_table_name
+		);
+
+		let mut block_heights = Vec::with_capacity(values.len());
+		let mut ext_indices = Vec::with_capacity(values.len());
+		let mut block_hashes = Vec::with_capacity(values.len());
+		let mut pallet_ids = Vec::with_capacity(values.len());
+		let mut variant_ids = Vec::with_capacity(values.len());
+		let mut calls = Vec::with_capacity(values.len());
+		let mut errors = Vec::with_capacity(values.len());
+		let mut created_ats = Vec::with_capacity(values.len());
+		for value in values {
+			block_heights.push(types::encode_u32(value.block_height));
+			ext_indices.push(types::encode_u32(value.ext_index));
+			block_hashes.push(types::encode_h256(&value.block_hash));
+			pallet_ids.push(value.pallet_id as i16);
+			variant_ids.push(value.variant_id as i16);
+			calls.push(value.call.clone());
+			errors.push(value.error.clone());
+			created_ats.push(value.created_at);
+		}
+
+		let _ = sqlx::query(&q)
+			.bind(block_heights)
+			.bind(ext_indices)
+			.bind(block_hashes)
+			.bind(pallet_ids)
+			.bind(variant_ids)
+			.bind(calls)
+			.bind(errors)
+			.bind(created_ats)
+			.execute(&mut **tx)
+			.await
+			.map_err(|e| e.to_string())?;
+
+		Ok(())
+	}
+
+	/// Removes the records of blocks that are written again, so only extrinsics that still fail are kept.
+	pub async fn delete_many(
+		block_heights: &[u32],
+		db: &Database,
+		tx: &mut Transaction<'_, Postgres>,
+	) -> Result<(), String> {
+		if block_heights.is_empty() {
+			return Ok(());
+		}
+
+		let q = std::format!("DELETE FROM {} WHERE block_height = ANY($1::BIGINT[])", db.dead_letter_table_name);
+		let block_heights: Vec<i64> = block_heights.iter().copied().map(types::encode_u32).collect();
+		let _ = sqlx::query(&q)
+			.bind(block_heights)
+			.execute(&mut **tx)
+			.await
+			.map_err(|e| e.to_string())?;
+
+		Ok(())
+	}
+}
+
+/// An extrinsic that could not be decoded or serialized. The raw call is kept so it can be processed again
+/// once the decoder is fixed.
+#[derive(Debug, Clone)]
+pub struct TableEntry {
+	/// In the DB this is stored as "BIGINT NOT NULL"
+	pub block_height: u32,
+	/// In the DB this is stored as "BIGINT NOT NULL"
+	pub ext_index: u32,
+	/// In the DB this is stored as "BYTEA NOT NULL"
+	pub block_hash: H256,
+	/// In the DB this is stored as "SMALLINT NOT NULL"
+	pub pallet_id: u8,
+	/// In the DB this is stored as "SMALLINT NOT NULL"
+	pub variant_id: u8,
+	/// In the DB this is stored as "BYTEA NOT NULL". SCALE encoded call.
+	pub call: Vec<u8>,
+	/// In the DB this is stored as "TEXT NOT NULL"
+	pub error: String,
+	/// In the DB this is stored as "TIMESTAMPTZ NOT NULL"
+	pub created_at: DateTime<Utc>,
+}
+
+impl TableEntry {
+	pub fn from_block_ext(block_height: u32, block_hash: H256, ext: &BlockEncodedExtrinsic, error: String) -> Self {
+		Self {
+			block_height,
+			ext_index: ext.metadata.ext_index,
+			block_hash,
+			pallet_id: ext.metadata.pallet_id,
+			variant_id: ext.metadata.variant_id,
+			call: ext.call.clone(),
+			error,
+			created_at: Utc::now(),
+		}
+	}
+}
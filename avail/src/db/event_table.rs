@@ -0,0 +1,91 @@
+use sqlx::{Postgres, Transaction};
+
+use crate::db::{Database, types};
+
+pub struct EventTable;
+impl EventTable {
+	/// Upserts all entries with a single statement.
+	pub async fn insert_many(
+		values: &[TableEntry],
+		db: &Database,
+		tx: &mut Transaction<'_, Postgres>,
+	) -> Result<(), String> {
+		if values.is_empty() {
+			return Ok(());
+		}
+
+		let q = std::format!(
+			"
+				INSERT INTO {} (
+					id,
+					event_index,
+					pallet_id,
+					variant_id,
+					event_name,
+					fields
+				)
+				SELECT id, event_index, pallet_id, variant_id, event_name, fields::JSONB FROM UNNEST(
+					$1::TEXT[]::NUMERIC(20)[],
+					$2::BIGINT[],
+					$3::SMALLINT[],
+					$4::SMALLINT[],
+					$5::TEXT[],
+					$6::TEXT[]
+				) AS t(id, event_index, pallet_id, variant_id, event_name, fields)
+				ON CONFLICT (id, event_index) DO UPDATE SET
+					pallet_id = EXCLUDED.pallet_id,
+					variant_id = EXCLUDED.variant_id,
+					event_name = EXCLUDED.event_name,
+					fields = EXCLUDED.fields
+			",
+			&db.event_table_name
+		);
+
+		let mut ids = Vec::with_capacity(values.len());
+		let mut event_indices = Vec::with_capacity(values.len());
+		let mut pallet_ids = Vec::with_capacity(values.len());
+		let mut variant_ids = Vec::with_capacity(values.len());
+		let mut event_names = Vec::with_capacity(values.len());
+		let mut fields = Vec::with_capacity(values.len());
+		for value in values {
+			ids.push(types::encode_u64(value.id));
+			event_indices.push(types::encode_u32(value.event_index));
+			pallet_ids.push(value.pallet_id as i16);
+			variant_ids.push(value.variant_id as i16);
+			event_names.push(value.event_name);
+			fields.push(value.fields.as_ref().map(|x| x.to_string()));
+		}
+
+		let _ = sqlx::query(&q)
+			.bind(ids)
+			.bind(event_indices)
+			.bind(pallet_ids)
+			.bind(variant_ids)
+			.bind(event_names)
+			.bind(fields)
+			.execute(&mut **tx)
+			.await
+			.map_err(|e| e.to_string())?;
+
+		Ok(())
+	}
+}
+
+pub struct TableEntry {
+	/// Main table id of the extrinsic that emitted the event
+	/// In the DB this is stored as "NUMERIC(20) NOT NULL" and together with event_index forms the primary key
+	pub id: u64,
+	/// Index of the event inside the block
+	/// In the DB this is stored as "BIGINT NOT NULL"
+	pub event_index: u32,
+	/// In the DB this is stored as "SMALLINT NOT NULL"
+	pub pallet_id: u8,
+	/// In the DB this is stored as "SMALLINT NOT NULL"
+	pub variant_id: u8,
+	/// Only set for events that the indexer knows how to decode. Example: "Vector::MessageSubmitted"
+	/// In the DB this is stored as "TEXT"
+	pub event_name: Option<&'static str>,
+	/// Decoded event fields. Only set for events that the indexer knows how to decode.
+	/// In the DB this is stored as "JSONB"
+	pub fields: Option<serde_json::Value>,
+}
@@ -1,30 +1,23 @@
 use avail_rust::H256;
+use sqlx::{Postgres, Transaction};
 
-use crate::{common::SerializedExecute, db::Database};
+use crate::{
+	common::SerializedExecute,
+	db::{Database, types},
+};
 
 pub struct ExecuteTable;
 impl ExecuteTable {
-	pub async fn create_table(db: &Database) -> Result<(), String> {
-		let q = std::format!(
-			"
-				CREATE TABLE IF NOT EXISTS {} (
-					id BIGINT PRIMARY KEY REFERENCES {},
-					\"type\" TEXT NOT NULL,
-					amount TEXT,
-					\"to\" TEXT NOT NULL,
-					slot BIGINT NOT NULL,
-					message_id NUMERIC(78) NOT NULL
-				);
-			",
-			&db.execute_table_name,
-			&db.main_table_name
-		);
-
-		sqlx::query(&q).execute(&db.conn).await.map_err(|e| e.to_string())?;
-		Ok(())
-	}
+	/// Upserts all entries with a single statement.
+	pub async fn insert_many(
+		values: &[TableEntry],
+		db: &Database,
+		tx: &mut Transaction<'_, Postgres>,
+	) -> Result<(), String> {
+		if values.is_empty() {
+			return Ok(());
+		}
 
-	pub async fn insert(value: TableEntry, db: &Database) -> Result<(), String> {
 		let q = std::format!(
 			"
 				INSERT INTO {} (
@@ -33,28 +26,79 @@ impl ExecuteTable {
 					amount,
 					\"to\",
 					slot,
-					message_id
+					message_id,
+					sender,
+					origin_domain,
+					destination_domain,
+					asset_id,
+					ext_success
+				)
+				SELECT * FROM UNNEST(
+					$1::TEXT[]::NUMERIC(20)[],
+					$2::TEXT[],
+					$3::TEXT[]::NUMERIC(39)[],
+					$4::BYTEA[],
+					$5::TEXT[]::NUMERIC(20)[],
+					$6::TEXT[]::NUMERIC(78)[],
+					$7::BYTEA[],
+					$8::BIGINT[],
+					$9::BIGINT[],
+					$10::BYTEA[],
+					$11::BOOL[]
 				)
-				VALUES ($1, $2, $3, $4, $5, $6)
 				ON CONFLICT (id) DO UPDATE SET
 					\"type\" = EXCLUDED.\"type\",
 					amount = EXCLUDED.amount,
 					\"to\" = EXCLUDED.\"to\",
 					slot = EXCLUDED.slot,
-					message_id = EXCLUDED.message_id
+					message_id = EXCLUDED.message_id,
+					sender = EXCLUDED.sender,
+					origin_domain = EXCLUDED.origin_domain,
+					destination_domain = EXCLUDED.destination_domain,
+					asset_id = EXCLUDED.asset_id,
+					ext_success = EXCLUDED.ext_success
 			",
 			&db.execute_table_name
 		);
 
-		let amount = value.amount.map(|x| std::format!("{}", x));
+		let mut ids = Vec::with_capacity(values.len());
+		let mut kinds = Vec::with_capacity(values.len());
+		let mut amounts = Vec::with_capacity(values.len());
+		let mut tos = Vec::with_capacity(values.len());
+		let mut slots = Vec::with_capacity(values.len());
+		let mut message_ids = Vec::with_capacity(values.len());
+		let mut senders = Vec::with_capacity(values.len());
+		let mut origin_domains = Vec::with_capacity(values.len());
+		let mut destination_domains = Vec::with_capacity(values.len());
+		let mut asset_ids = Vec::with_capacity(values.len());
+		let mut ext_successes = Vec::with_capacity(values.len());
+		for value in values {
+			ids.push(types::encode_u64(value.id));
+			kinds.push(value.kind.clone());
+			amounts.push(value.amount.map(types::encode_u128));
+			tos.push(types::encode_h256(&value.to));
+			slots.push(types::encode_u64(value.slot));
+			message_ids.push(types::encode_u64(value.message_id));
+			senders.push(types::encode_h256(&value.sender));
+			origin_domains.push(types::encode_u32(value.origin_domain));
+			destination_domains.push(types::encode_u32(value.destination_domain));
+			asset_ids.push(value.asset_id.as_ref().map(types::encode_h256));
+			ext_successes.push(value.ext_success);
+		}
+
 		let _ = sqlx::query(&q)
-			.bind(value.id as i64)
-			.bind(value.kind)
-			.bind(amount)
-			.bind(std::format!("{:?}", value.to))
-			.bind(value.slot as i64)
-			.bind(value.message_id as i64)
-			.execute(&db.conn)
+			.bind(ids)
+			.bind(kinds)
+			.bind(amounts)
+			.bind(tos)
+			.bind(slots)
+			.bind(message_ids)
+			.bind(senders)
+			.bind(origin_domains)
+			.bind(destination_domains)
+			.bind(asset_ids)
+			.bind(ext_successes)
+			.execute(&mut **tx)
 			.await
 			.map_err(|e| e.to_string())?;
 
@@ -63,22 +107,34 @@ impl ExecuteTable {
 }
 
 pub struct TableEntry {
-	/// In the DB this is stored as "BIGINT PRIMARY KEY"
+	/// In the DB this is stored as "NUMERIC(20) PRIMARY KEY"
 	pub id: u64,
 	/// In the DB this is stored as "TEXT NOT NULL"
 	pub kind: String,
-	/// In the DB this is stored as "TEXT"
+	/// In the DB this is stored as "NUMERIC(39)"
 	pub amount: Option<u128>,
-	/// In the DB this is stored as "TEXT NOT NULL"
+	/// In the DB this is stored as "BYTEA NOT NULL"
 	pub to: H256,
-	/// In the DB this is stored as "BIGINT NOT NULL"
+	/// In the DB this is stored as "NUMERIC(20) NOT NULL"
 	pub slot: u64,
-	/// In the DB this is stored as "BIGINT NOT NULL"
+	/// In the DB this is stored as "NUMERIC(78) NOT NULL"
 	pub message_id: u64,
+	/// In the DB this is stored as "BYTEA"
+	pub sender: H256,
+	/// In the DB this is stored as "BIGINT"
+	pub origin_domain: u32,
+	/// In the DB this is stored as "BIGINT"
+	pub destination_domain: u32,
+	/// Only set for Fungible Token messages
+	/// In the DB this is stored as "BYTEA"
+	pub asset_id: Option<H256>,
+	/// Copy of the main table ext_success. A message can only be executed successfully once.
+	/// In the DB this is stored as "nullable BOOL"
+	pub ext_success: Option<bool>,
 }
 
 impl TableEntry {
-	pub fn from_call(id: u64, call: &SerializedExecute) -> Self {
+	pub fn from_call(id: u64, call: &SerializedExecute, ext_success: Option<bool>) -> Self {
 		Self {
 			id,
 			kind: call.addr_message.message.kind().to_string(),
@@ -86,6 +142,11 @@ impl TableEntry {
 			to: call.addr_message.to,
 			slot: call.slot,
 			message_id: call.addr_message.id,
+			sender: call.addr_message.from,
+			origin_domain: call.addr_message.origin_domain,
+			destination_domain: call.addr_message.destination_domain,
+			asset_id: call.addr_message.message.asset_id(),
+			ext_success,
 		}
 	}
 }
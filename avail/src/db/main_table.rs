@@ -1,36 +1,12 @@
-use crate::db::Database;
+use crate::db::{Database, types};
 use avail_rust::{H256, block::BlockEncodedExtrinsic};
 use sqlx::{
-	Row,
+	Postgres, Transaction,
 	types::chrono::{DateTime, Utc},
 };
 
 pub struct MainTable;
 impl MainTable {
-	pub async fn create_table(db: &Database) -> Result<(), String> {
-		let q = std::format!(
-			"
-				CREATE TABLE IF NOT EXISTS {} (
-					id BIGINT PRIMARY KEY,
-					block_height INTEGER NOT NULL,
-					block_hash TEXT NOT NULL,
-					block_timestamp TIMESTAMPTZ NOT NULL,
-					ext_index INTEGER NOT NULL,
-					ext_hash TEXT NOT NULL,
-					signature_address TEXT,
-					pallet_id SMALLINT NOT NULL,
-					variant_id SMALLINT NOT NULL,
-					ext_success BOOL,
-					ext_call TEXT NOT NULL
-				);
-			",
-			db.main_table_name
-		);
-
-		sqlx::query(&q).execute(&db.conn).await.map_err(|e| e.to_string())?;
-		Ok(())
-	}
-
 	pub async fn find_highest_block_height(db: &Database) -> Result<Option<u32>, String> {
 		let q = std::format!("SELECT MAX(block_height) FROM {}", db.main_table_name);
 		let row = sqlx::query(&q)
@@ -42,15 +18,79 @@ impl MainTable {
 			return Ok(None);
 		};
 
-		let block_height = row
-			.try_get::<Option<i64>, _>("max")
-			.map_err(|e| std::format!("Failed to convert block_height. Error: {}", e.to_string()))?
-			.map(|x| x as u32);
+		types::read_optional_u32(&row, "max")
+	}
+
+	pub async fn find_ids(from: u32, to: u32, db: &Database) -> Result<Vec<(u32, u64)>, String> {
+		let q = std::format!(
+			"SELECT block_height, id::TEXT AS id FROM {} WHERE block_height BETWEEN $1 AND $2",
+			db.main_table_name
+		);
+		let rows = sqlx::query(&q)
+			.bind(types::encode_u32(from))
+			.bind(types::encode_u32(to))
+			.fetch_all(&db.conn)
+			.await
+			.map_err(|e| e.to_string())?;
+
+		let mut result = Vec::with_capacity(rows.len());
+		for row in rows {
+			result.push((types::read_u32(&row, "block_height")?, types::read_u64(&row, "id")?));
+		}
+
+		Ok(result)
+	}
+
+	/// Deletes the entries together with the send message, execute, event, call and bridge message rows that
+	/// reference them.
+	pub async fn delete_many(ids: &[u64], db: &Database, tx: &mut Transaction<'_, Postgres>) -> Result<(), String> {
+		if ids.is_empty() {
+			return Ok(());
+		}
+
+		let ids: Vec<String> = ids.iter().copied().map(types::encode_u64).collect();
+		let q = std::format!(
+			"
+				DELETE FROM {} WHERE
+					send_ext_id = ANY($1::TEXT[]::NUMERIC(20)[]) OR execute_ext_id = ANY($1::TEXT[]::NUMERIC(20)[])
+			",
+			&db.bridge_message_table_name
+		);
+		let _ = sqlx::query(&q)
+			.bind(&ids)
+			.execute(&mut **tx)
+			.await
+			.map_err(|e| e.to_string())?;
+
+		let mut tables = vec![
+			&db.event_table_name,
+			&db.send_message_table_name,
+			&db.execute_table_name,
+		];
+		tables.extend(db.call_table_names.iter());
+		tables.push(&db.main_table_name);
+		for table in tables {
+			let q = std::format!("DELETE FROM {} WHERE id = ANY($1::TEXT[]::NUMERIC(20)[])", table);
+			let _ = sqlx::query(&q)
+				.bind(&ids)
+				.execute(&mut **tx)
+				.await
+				.map_err(|e| e.to_string())?;
+		}
 
-		Ok(block_height)
+		Ok(())
 	}
 
-	pub async fn insert(value: TableEntry, db: &Database) -> Result<(), String> {
+	/// Upserts all entries with a single statement.
+	pub async fn insert_many(
+		values: &[TableEntry],
+		db: &Database,
+		tx: &mut Transaction<'_, Postgres>,
+	) -> Result<(), String> {
+		if values.is_empty() {
+			return Ok(());
+		}
+
 		let q = std::format!(
 			"
 				INSERT INTO {} (
@@ -66,7 +106,19 @@ impl MainTable {
 					ext_success,
 					ext_call
 				)
-				VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+				SELECT * FROM UNNEST(
+					$1::TEXT[]::NUMERIC(20)[],
+					$2::BIGINT[],
+					$3::BYTEA[],
+					$4::TIMESTAMPTZ[],
+					$5::BIGINT[],
+					$6::BYTEA[],
+					$7::TEXT[],
+					$8::SMALLINT[],
+					$9::SMALLINT[],
+					$10::BOOL[],
+					$11::TEXT[]
+				)
 				ON CONFLICT (id) DO UPDATE SET
 					block_height = EXCLUDED.block_height,
 					block_hash = EXCLUDED.block_hash,
@@ -81,21 +133,48 @@ impl MainTable {
 			",
 			db.main_table_name
 		);
-		let block_timestamp = DateTime::<Utc>::from_timestamp(value.block_timestamp as i64, 0)
-			.ok_or_else(|| "Failed to convert block_timestamp to chrono DateTime".to_string())?;
+
+		let mut ids = Vec::with_capacity(values.len());
+		let mut block_heights = Vec::with_capacity(values.len());
+		let mut block_hashes = Vec::with_capacity(values.len());
+		let mut block_timestamps = Vec::with_capacity(values.len());
+		let mut ext_indices = Vec::with_capacity(values.len());
+		let mut ext_hashes = Vec::with_capacity(values.len());
+		let mut signature_addresses = Vec::with_capacity(values.len());
+		let mut pallet_ids = Vec::with_capacity(values.len());
+		let mut variant_ids = Vec::with_capacity(values.len());
+		let mut ext_successes = Vec::with_capacity(values.len());
+		let mut ext_calls = Vec::with_capacity(values.len());
+		for value in values {
+			let block_timestamp = DateTime::<Utc>::from_timestamp(value.block_timestamp as i64, 0)
+				.ok_or_else(|| "Failed to convert block_timestamp to chrono DateTime".to_string())?;
+
+			ids.push(types::encode_u64(value.id));
+			block_heights.push(types::encode_u32(value.block_height));
+			block_hashes.push(types::encode_h256(&value.block_hash));
+			block_timestamps.push(block_timestamp);
+			ext_indices.push(types::encode_u32(value.ext_index));
+			ext_hashes.push(types::encode_h256(&value.ext_hash));
+			signature_addresses.push(value.signature_address.clone());
+			pallet_ids.push(value.pallet_id as i16);
+			variant_ids.push(value.variant_id as i16);
+			ext_successes.push(value.ext_success);
+			ext_calls.push(value.ext_call.clone());
+		}
+
 		let _ = sqlx::query(&q)
-			.bind(value.id as i64)
-			.bind(value.block_height as i32)
-			.bind(std::format!("{:?}", value.block_hash))
-			.bind(block_timestamp)
-			.bind(value.ext_index as i32)
-			.bind(std::format!("{:?}", value.ext_hash))
-			.bind(value.signature_address)
-			.bind(value.pallet_id as i16)
-			.bind(value.variant_id as i16)
-			.bind(value.ext_success)
-			.bind(value.ext_call)
-			.execute(&db.conn)
+			.bind(ids)
+			.bind(block_heights)
+			.bind(block_hashes)
+			.bind(block_timestamps)
+			.bind(ext_indices)
+			.bind(ext_hashes)
+			.bind(signature_addresses)
+			.bind(pallet_ids)
+			.bind(variant_ids)
+			.bind(ext_successes)
+			.bind(ext_calls)
+			.execute(&mut **tx)
 			.await
 			.map_err(|e| e.to_string())?;
 
@@ -104,17 +183,17 @@ impl MainTable {
 }
 
 pub struct TableEntry {
-	/// In the DB this is stored as "BIGINT PRIMARY KEY"
+	/// In the DB this is stored as "NUMERIC(20) PRIMARY KEY"
 	pub id: u64,
-	/// In the DB this is stored as "INTEGER NOT NULL"
+	/// In the DB this is stored as "BIGINT NOT NULL"
 	pub block_height: u32,
-	/// In the DB this is stored as "TEXT NOT NULL"
+	/// In the DB this is stored as "BYTEA NOT NULL"
 	pub block_hash: H256,
 	/// In the DB this is stored as "TIMESTAMPTZ NOT NULL"
 	pub block_timestamp: u64,
-	/// In the DB this is stored as "INTEGER NOT NULL"
+	/// In the DB this is stored as "BIGINT NOT NULL"
 	pub ext_index: u32,
-	/// In the DB this is stored as "TEXT NOT NULL"
+	/// In the DB this is stored as "BYTEA NOT NULL"
 	pub ext_hash: H256,
 	// ss58 address
 	/// In the DB this is stored as "nullable TEXT"
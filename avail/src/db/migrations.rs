@@ -0,0 +1,650 @@
+use crate::db::Database;
+use sqlx::Row;
+use tracing::info;
+
+/// Table that records which migrations have been applied.
+///
+/// Several indexers with different table names can share one database, so every
+/// row is scoped by the main table name.
+pub const SCHEMA_VERSION_TABLE: &str = "schema_version";
+
+/// A numbered, forward-only schema change.
+///
+/// Migrations are never edited once released. Changes to the schema are done by appending a new one.
+pub struct Migration {
+	pub version: u32,
+	pub description: &'static str,
+	pub statements: fn(&Database) -> Vec<String>,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+	Migration {
+		version: 1,
+		description: "Create main, execute, send message and checkpoint tables",
+		statements: v1_initial_tables,
+	},
+	Migration {
+		version: 2,
+		description: "Add domain, asset_id, payload and sender to send message table",
+		statements: v2_send_message_envelope,
+	},
+	Migration {
+		version: 3,
+		description: "Add sender, origin_domain, destination_domain and asset_id to execute table",
+		statements: v3_execute_envelope,
+	},
+	Migration {
+		version: 4,
+		description: "Add status and status_source to send message table",
+		statements: v4_send_message_status,
+	},
+	Migration {
+		version: 5,
+		description: "Create event table",
+		statements: v5_event_table,
+	},
+	Migration {
+		version: 6,
+		description: "Create bridge message table",
+		statements: v6_bridge_message_table,
+	},
+	Migration {
+		version: 7,
+		description: "Add lookup indexes and unique successful execute message_id",
+		statements: v7_lookup_indexes,
+	},
+	Migration {
+		version: 8,
+		description: "Store amounts as NUMERIC(39) and hashes as BYTEA",
+		statements: v8_numeric_amounts_and_bytea_hashes,
+	},
+	Migration {
+		version: 9,
+		description: "Store unsigned identifiers without wrapping and repair wrapped message ids",
+		statements: v9_unsigned_identifiers,
+	},
+	Migration {
+		version: 10,
+		description: "Create block table",
+		statements: v10_block_table,
+	},
+	Migration {
+		version: 11,
+		description: "Create quarantine table",
+		statements: v11_quarantine_table,
+	},
+	Migration {
+		version: 12,
+		description: "Create dead letter table",
+		statements: v12_dead_letter_table,
+	},
+];
+
+/// Applies every migration that has not been applied yet. Each migration runs in its own transaction.
+pub async fn run(db: &Database) -> Result<(), String> {
+	create_schema_version_table(db).await?;
+
+	let mut current_version = current_version(db).await?;
+	for migration in MIGRATIONS {
+		if migration.version <= current_version {
+			continue;
+		}
+
+		let mut tx = db.conn.begin().await.map_err(|e| e.to_string())?;
+
+		// Makes sure that two indexers starting at the same time don't apply the same migration twice.
+		sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+			.bind(&db.main_table_name)
+			.execute(&mut *tx)
+			.await
+			.map_err(|e| e.to_string())?;
+
+		let q = std::format!("SELECT 1 FROM {} WHERE table_name = $1 AND version = $2", SCHEMA_VERSION_TABLE);
+		let already_applied = sqlx::query(&q)
+			.bind(&db.main_table_name)
+			.bind(migration.version as i32)
+			.fetch_optional(&mut *tx)
+			.await
+			.map_err(|e| e.to_string())?
+			.is_some();
+
+		if !already_applied {
+			for statement in (migration.statements)(db) {
+				sqlx::query(&statement).execute(&mut *tx).await.map_err(|e| {
+					std::format!("Failed to apply migration {}. Error: {}", migration.version, e.to_string())
+				})?;
+			}
+
+			let q = std::format!(
+				"INSERT INTO {} (table_name, version, description, applied_at) VALUES ($1, $2, $3, NOW())",
+				SCHEMA_VERSION_TABLE
+			);
+			sqlx::query(&q)
+				.bind(&db.main_table_name)
+				.bind(migration.version as i32)
+				.bind(migration.description)
+				.execute(&mut *tx)
+				.await
+				.map_err(|e| e.to_string())?;
+		}
+
+		tx.commit().await.map_err(|e| e.to_string())?;
+		current_version = migration.version;
+
+		if !already_applied {
+			info!(version = migration.version, description = migration.description, "Migration applied");
+		}
+	}
+
+	Ok(())
+}
+
+async fn create_schema_version_table(db: &Database) -> Result<(), String> {
+	let q = std::format!(
+		"
+			CREATE TABLE IF NOT EXISTS {} (
+				table_name TEXT NOT NULL,
+				version INTEGER NOT NULL,
+				description TEXT NOT NULL,
+				applied_at TIMESTAMPTZ NOT NULL,
+				PRIMARY KEY (table_name, version)
+			);
+		",
+		SCHEMA_VERSION_TABLE
+	);
+
+	sqlx::query(&q).execute(&db.conn).await.map_err(|e| e.to_string())?;
+	Ok(())
+}
+
+async fn current_version(db: &Database) -> Result<u32, String> {
+	let q = std::format!("SELECT MAX(version) FROM {} WHERE table_name = $1", SCHEMA_VERSION_TABLE);
+	let row = sqlx::query(&q)
+		.bind(&db.main_table_name)
+		.fetch_one(&db.conn)
+		.await
+		.map_err(|e| e.to_string())?;
+
+	let version = row
+		.try_get::<Option<i32>, _>("max")
+		.map_err(|e| std::format!("Failed to convert schema version. Error: {}", e.to_string()))?
+		.unwrap_or(0);
+
+	Ok(version as u32)
+}
+
+/// Matches the tables that were created before migrations existed so existing deployments
+/// can adopt them without any change.
+fn v1_initial_tables(db: &Database) -> Vec<String> {
+	vec![
+		std::format!(
+			"
+				CREATE TABLE IF NOT EXISTS {} (
+					id BIGINT PRIMARY KEY,
+					block_height INTEGER NOT NULL,
+					block_hash TEXT NOT NULL,
+					block_timestamp TIMESTAMPTZ NOT NULL,
+					ext_index INTEGER NOT NULL,
+					ext_hash TEXT NOT NULL,
+					signature_address TEXT,
+					pallet_id SMALLINT NOT NULL,
+					variant_id SMALLINT NOT NULL,
+					ext_success BOOL,
+					ext_call TEXT NOT NULL
+				);
+			",
+			db.main_table_name
+		),
+		std::format!(
+			"
+				CREATE TABLE IF NOT EXISTS {} (
+					id BIGINT PRIMARY KEY REFERENCES {},
+					\"type\" TEXT NOT NULL,
+					amount TEXT,
+					\"to\" TEXT NOT NULL,
+					slot BIGINT NOT NULL,
+					message_id NUMERIC(78) NOT NULL
+				);
+			",
+			db.execute_table_name,
+			db.main_table_name
+		),
+		std::format!(
+			"
+				CREATE TABLE IF NOT EXISTS {} (
+					id BIGINT PRIMARY KEY REFERENCES {},
+					\"type\" TEXT NOT NULL,
+					amount TEXT,
+					\"to\" TEXT NOT NULL
+				);
+			",
+			db.send_message_table_name,
+			db.main_table_name
+		),
+		std::format!(
+			"
+				CREATE TABLE IF NOT EXISTS {} (
+					name TEXT PRIMARY KEY,
+					block_height INTEGER NOT NULL,
+					block_hash TEXT NOT NULL,
+					updated_at TIMESTAMPTZ NOT NULL
+				);
+			",
+			db.checkpoint_table_name
+		),
+	]
+}
+
+/// Existing rows are backfilled from the JSON encoded call and the main table signature address.
+fn v2_send_message_envelope(db: &Database) -> Vec<String> {
+	let table = &db.send_message_table_name;
+	vec![
+		std::format!(
+			"
+				ALTER TABLE {}
+					ADD COLUMN IF NOT EXISTS domain INTEGER,
+					ADD COLUMN IF NOT EXISTS asset_id TEXT,
+					ADD COLUMN IF NOT EXISTS payload TEXT,
+					ADD COLUMN IF NOT EXISTS sender TEXT;
+			",
+			table
+		),
+		std::format!(
+			"
+				UPDATE {} AS s SET
+					domain = (m.ext_call::jsonb ->> 'domain')::INTEGER,
+					asset_id = m.ext_call::jsonb -> 'message' -> 'FungibleToken' ->> 'asset_id',
+					payload = m.ext_call::jsonb -> 'message' ->> 'ArbitraryMessage',
+					sender = m.signature_address
+				FROM {} AS m
+				WHERE s.id = m.id AND s.domain IS NULL;
+			",
+			table,
+			db.main_table_name
+		),
+		std::format!("CREATE INDEX IF NOT EXISTS {}_domain_idx ON {} (domain);", table, table),
+		std::format!("CREATE INDEX IF NOT EXISTS {}_asset_id_idx ON {} (asset_id);", table, table),
+	]
+}
+
+/// Existing rows are backfilled from the JSON encoded call.
+fn v3_execute_envelope(db: &Database) -> Vec<String> {
+	let table = &db.execute_table_name;
+	vec![
+		std::format!(
+			"
+				ALTER TABLE {}
+					ADD COLUMN IF NOT EXISTS sender TEXT,
+					ADD COLUMN IF NOT EXISTS origin_domain INTEGER,
+					ADD COLUMN IF NOT EXISTS destination_domain INTEGER,
+					ADD COLUMN IF NOT EXISTS asset_id TEXT;
+			",
+			table
+		),
+		std::format!(
+			"
+				UPDATE {} AS e SET
+					sender = m.ext_call::jsonb -> 'addr_message' ->> 'from',
+					origin_domain = (m.ext_call::jsonb -> 'addr_message' ->> 'origin_domain')::INTEGER,
+					destination_domain = (m.ext_call::jsonb -> 'addr_message' ->> 'destination_domain')::INTEGER,
+					asset_id = m.ext_call::jsonb -> 'addr_message' -> 'message' -> 'FungibleToken' ->> 'asset_id'
+				FROM {} AS m
+				WHERE e.id = m.id AND e.sender IS NULL;
+			",
+			table,
+			db.main_table_name
+		),
+		std::format!("CREATE INDEX IF NOT EXISTS {}_sender_idx ON {} (sender);", table, table),
+		std::format!("CREATE INDEX IF NOT EXISTS {}_asset_id_idx ON {} (asset_id);", table, table),
+	]
+}
+
+/// Rows written before this migration never include failed send messages so they are all submitted.
+fn v4_send_message_status(db: &Database) -> Vec<String> {
+	let table = &db.send_message_table_name;
+	vec![
+		std::format!(
+			"
+				ALTER TABLE {}
+					ADD COLUMN IF NOT EXISTS status TEXT NOT NULL DEFAULT 'submitted',
+					ADD COLUMN IF NOT EXISTS status_source TEXT;
+			",
+			table
+		),
+		std::format!("CREATE INDEX IF NOT EXISTS {}_status_idx ON {} (status);", table, table),
+	]
+}
+
+fn v5_event_table(db: &Database) -> Vec<String> {
+	let table = &db.event_table_name;
+	vec![
+		std::format!(
+			"
+				CREATE TABLE IF NOT EXISTS {} (
+					id BIGINT NOT NULL REFERENCES {},
+					event_index INTEGER NOT NULL,
+					pallet_id SMALLINT NOT NULL,
+					variant_id SMALLINT NOT NULL,
+					event_name TEXT,
+					fields JSONB,
+					PRIMARY KEY (id, event_index)
+				);
+			",
+			table,
+			db.main_table_name
+		),
+		std::format!("CREATE INDEX IF NOT EXISTS {}_event_name_idx ON {} (event_name);", table, table),
+	]
+}
+
+/// Existing send message and execute rows are used to seed the table.
+fn v6_bridge_message_table(db: &Database) -> Vec<String> {
+	let table = &db.bridge_message_table_name;
+	vec![
+		std::format!(
+			"
+				CREATE TABLE IF NOT EXISTS {} (
+					direction TEXT NOT NULL,
+					message_id NUMERIC(20) NOT NULL,
+					status TEXT NOT NULL,
+					slot BIGINT,
+					send_ext_id BIGINT,
+					execute_ext_id BIGINT,
+					updated_at TIMESTAMPTZ NOT NULL,
+					PRIMARY KEY (direction, message_id)
+				);
+			",
+			table
+		),
+		std::format!("CREATE INDEX IF NOT EXISTS {}_status_idx ON {} (status);", table, table),
+		std::format!(
+			"
+				INSERT INTO {} (direction, message_id, status, slot, send_ext_id, execute_ext_id, updated_at)
+				SELECT 'ethereum_to_avail', e.message_id, 'bridged', e.slot, NULL, e.id, NOW()
+				FROM {} AS e
+				JOIN {} AS m ON m.id = e.id
+				WHERE m.ext_success IS TRUE
+				ON CONFLICT DO NOTHING;
+			",
+			table,
+			db.execute_table_name,
+			db.main_table_name
+		),
+		// Same as `ExecuteHandler`: a failed Execute, or one without events, only shows the message is claimable.
+		// Runs after the successful ones so those win.
+		std::format!(
+			"
+				INSERT INTO {} (direction, message_id, status, slot, send_ext_id, execute_ext_id, updated_at)
+				SELECT DISTINCT ON (e.message_id) 'ethereum_to_avail', e.message_id, 'claim_ready', e.slot, NULL, NULL, NOW()
+				FROM {} AS e
+				JOIN {} AS m ON m.id = e.id
+				WHERE m.ext_success IS NOT TRUE
+				ORDER BY e.message_id, e.id DESC
+				ON CONFLICT DO NOTHING;
+			",
+			table,
+			db.execute_table_name,
+			db.main_table_name
+		),
+		std::format!(
+			"
+				INSERT INTO {} (direction, message_id, status, slot, send_ext_id, execute_ext_id, updated_at)
+				SELECT 'avail_to_ethereum', (ev.fields ->> 'message_id')::NUMERIC(20), 'initiated', NULL, s.id, NULL, NOW()
+				FROM {} AS s
+				JOIN {} AS ev ON ev.id = s.id AND ev.event_name = 'Vector::MessageSubmitted'
+				WHERE s.status = 'submitted'
+				ON CONFLICT DO NOTHING;
+			",
+			table,
+			db.send_message_table_name,
+			db.event_table_name
+		),
+	]
+}
+
+/// Only successful Execute extrinsics have a unique message_id. Failed ones can share it with the successful one,
+/// and ones whose events could not be fetched have no known outcome. Successful duplicates that are already
+/// stored are removed first, keeping the earliest one.
+fn v7_lookup_indexes(db: &Database) -> Vec<String> {
+	let main = &db.main_table_name;
+	let send_message = &db.send_message_table_name;
+	let execute = &db.execute_table_name;
+	vec![
+		std::format!("CREATE INDEX IF NOT EXISTS {}_block_height_idx ON {} (block_height);", main, main),
+		std::format!("CREATE INDEX IF NOT EXISTS {}_ext_hash_idx ON {} (ext_hash);", main, main),
+		std::format!("CREATE INDEX IF NOT EXISTS {}_signature_address_idx ON {} (signature_address);", main, main),
+		std::format!("CREATE INDEX IF NOT EXISTS {}_to_idx ON {} (\"to\");", send_message, send_message),
+		std::format!("CREATE INDEX IF NOT EXISTS {}_to_idx ON {} (\"to\");", execute, execute),
+		std::format!("ALTER TABLE {} ADD COLUMN IF NOT EXISTS ext_success BOOL;", execute),
+		std::format!("UPDATE {} AS e SET ext_success = m.ext_success FROM {} AS m WHERE e.id = m.id;", execute, main),
+		std::format!(
+			"
+				DELETE FROM {} AS a USING {} AS b
+				WHERE a.message_id = b.message_id AND a.ext_success IS TRUE AND b.ext_success IS TRUE AND a.id > b.id;
+			",
+			execute,
+			execute
+		),
+		std::format!(
+			"CREATE UNIQUE INDEX IF NOT EXISTS {}_message_id_idx ON {} (message_id) WHERE ext_success IS TRUE;",
+			execute,
+			execute
+		),
+	]
+}
+
+/// Hashes were stored as "0x" prefixed lowercase hex strings and amounts as decimal strings.
+fn v8_numeric_amounts_and_bytea_hashes(db: &Database) -> Vec<String> {
+	let amount_to_numeric = "amount TYPE NUMERIC(39) USING amount::NUMERIC(39)";
+	vec![
+		std::format!(
+			"ALTER TABLE {} ALTER COLUMN {}, ALTER COLUMN {};",
+			db.main_table_name,
+			hex_to_bytea("block_hash"),
+			hex_to_bytea("ext_hash")
+		),
+		std::format!(
+			"ALTER TABLE {} ALTER COLUMN {}, ALTER COLUMN {}, ALTER COLUMN {};",
+			db.send_message_table_name,
+			amount_to_numeric,
+			hex_to_bytea("\"to\""),
+			hex_to_bytea("asset_id")
+		),
+		std::format!(
+			"ALTER TABLE {} ALTER COLUMN {}, ALTER COLUMN {}, ALTER COLUMN {}, ALTER COLUMN {};",
+			db.execute_table_name,
+			amount_to_numeric,
+			hex_to_bytea("\"to\""),
+			hex_to_bytea("sender"),
+			hex_to_bytea("asset_id")
+		),
+		std::format!("ALTER TABLE {} ALTER COLUMN {};", db.checkpoint_table_name, hex_to_bytea("block_hash")),
+	]
+}
+
+fn hex_to_bytea(column: &str) -> String {
+	std::format!("{} TYPE BYTEA USING decode(substring({} FROM 3), 'hex')", column, column)
+}
+
+/// u64 values were bound with `as i64` and u32 values with `as i32`, so values above the signed maximum
+/// wrapped to negative numbers. Converting the column reverses the wrap. Foreign keys are dropped while the
+/// referenced and referencing columns change type and are added back afterwards.
+fn v9_unsigned_identifiers(db: &Database) -> Vec<String> {
+	let main = &db.main_table_name;
+	let children = [
+		&db.send_message_table_name,
+		&db.execute_table_name,
+		&db.event_table_name,
+	];
+
+	let mut statements = Vec::new();
+	for child in children {
+		statements.push(std::format!("ALTER TABLE {} DROP CONSTRAINT IF EXISTS {}_id_fkey;", child, child));
+	}
+	statements.push(std::format!(
+		"ALTER TABLE {} ALTER COLUMN {}, ALTER COLUMN {}, ALTER COLUMN {};",
+		main,
+		unwrap_u64("id"),
+		unwrap_u32("block_height"),
+		unwrap_u32("ext_index")
+	));
+	statements.push(std::format!(
+		"ALTER TABLE {} ALTER COLUMN {}, ALTER COLUMN {};",
+		db.send_message_table_name,
+		unwrap_u64("id"),
+		unwrap_u32("domain")
+	));
+	statements.push(std::format!(
+		"ALTER TABLE {} ALTER COLUMN {}, ALTER COLUMN {}, ALTER COLUMN {}, ALTER COLUMN {};",
+		db.execute_table_name,
+		unwrap_u64("id"),
+		unwrap_u64("slot"),
+		unwrap_u32("origin_domain"),
+		unwrap_u32("destination_domain")
+	));
+	statements.push(std::format!(
+		"ALTER TABLE {} ALTER COLUMN {}, ALTER COLUMN {};",
+		db.event_table_name,
+		unwrap_u64("id"),
+		unwrap_u32("event_index")
+	));
+	statements.push(std::format!(
+		"ALTER TABLE {} ALTER COLUMN {}, ALTER COLUMN {}, ALTER COLUMN {};",
+		db.bridge_message_table_name,
+		unwrap_u64("slot"),
+		unwrap_u64("send_ext_id"),
+		unwrap_u64("execute_ext_id")
+	));
+	statements.push(std::format!(
+		"ALTER TABLE {} ALTER COLUMN {};",
+		db.checkpoint_table_name,
+		unwrap_u32("block_height")
+	));
+	for child in children {
+		statements.push(std::format!(
+			"ALTER TABLE {} ADD CONSTRAINT {}_id_fkey FOREIGN KEY (id) REFERENCES {};",
+			child,
+			child,
+			main
+		));
+	}
+
+	// Message ids were written into a NUMERIC column, so they kept their wrapped value. They are rewritten from the
+	// JSON encoded call in the main table. `repair-ids` does the same for rows written after this migration.
+	// The statements are copied on purpose: like every released migration they are frozen, while
+	// `repair::statements` is the only copy that is maintained and may change together with the schema.
+	let execute = &db.execute_table_name;
+	let bridge_message = &db.bridge_message_table_name;
+	let call_message_id = "(m.ext_call::jsonb -> 'addr_message' ->> 'id')::NUMERIC(78)";
+	statements.push(std::format!(
+		"
+			UPDATE {} AS e SET message_id = {}
+			FROM {} AS m
+			WHERE e.id = m.id AND e.message_id <> {};
+		",
+		execute,
+		call_message_id,
+		main,
+		call_message_id
+	));
+	// The correct row might already exist if the message was seen again after the wrapped one was written.
+	statements.push(std::format!(
+		"
+			DELETE FROM {} AS b
+			USING {} AS m
+			WHERE b.direction = 'ethereum_to_avail' AND b.execute_ext_id = m.id AND b.message_id <> {}
+				AND EXISTS (SELECT 1 FROM {} AS c WHERE c.direction = b.direction AND c.message_id = {});
+		",
+		bridge_message,
+		main,
+		call_message_id,
+		bridge_message,
+		call_message_id
+	));
+	statements.push(std::format!(
+		"
+			UPDATE {} AS b SET message_id = {}, updated_at = NOW()
+			FROM {} AS m
+			WHERE b.direction = 'ethereum_to_avail' AND b.execute_ext_id = m.id AND b.message_id <> {};
+		",
+		bridge_message,
+		call_message_id,
+		main,
+		call_message_id
+	));
+	statements
+}
+
+fn unwrap_u64(column: &str) -> String {
+	std::format!(
+		"{} TYPE NUMERIC(20) USING (CASE WHEN {} < 0 THEN {}::NUMERIC + 18446744073709551616 ELSE {}::NUMERIC END)",
+		column,
+		column,
+		column,
+		column
+	)
+}
+
+fn unwrap_u32(column: &str) -> String {
+	std::format!(
+		"{} TYPE BIGINT USING (CASE WHEN {} < 0 THEN {}::BIGINT + 4294967296 ELSE {}::BIGINT END)",
+		column,
+		column,
+		column,
+		column
+	)
+}
+
+/// Only blocks processed from now on get a row. Older blocks can be backfilled by reindexing them.
+fn v10_block_table(db: &Database) -> Vec<String> {
+	let table = &db.block_table_name;
+	vec![
+		std::format!(
+			"
+				CREATE TABLE IF NOT EXISTS {} (
+					block_height BIGINT PRIMARY KEY,
+					block_hash BYTEA NOT NULL,
+					parent_hash BYTEA NOT NULL,
+					block_timestamp TIMESTAMPTZ NOT NULL,
+					tracked_ext_count BIGINT NOT NULL
+				);
+			",
+			table
+		),
+		std::format!("CREATE INDEX IF NOT EXISTS {}_block_hash_idx ON {} (block_hash);", table, table),
+	]
+}
+
+fn v11_quarantine_table(db: &Database) -> Vec<String> {
+	vec![std::format!(
+		"
+			CREATE TABLE IF NOT EXISTS {} (
+				block_height BIGINT PRIMARY KEY,
+				block_hash BYTEA NOT NULL,
+				error TEXT NOT NULL,
+				attempts BIGINT NOT NULL,
+				quarantined_at TIMESTAMPTZ NOT NULL
+			);
+		",
+		db.quarantine_table_name
+	)]
+}
+
+fn v12_dead_letter_table(db: &Database) -> Vec<String> {
+	vec![std::format!(
+		"
+			CREATE TABLE IF NOT EXISTS {} (
+				block_height BIGINT NOT NULL,
+				ext_index BIGINT NOT NULL,
+				block_hash BYTEA NOT NULL,
+				pallet_id SMALLINT NOT NULL,
+				variant_id SMALLINT NOT NULL,
+				call BYTEA NOT NULL,
+				error TEXT NOT NULL,
+				created_at TIMESTAMPTZ NOT NULL,
+				PRIMARY KEY (block_height, ext_index)
+			);
+		",
+		db.dead_letter_table_name
+	)]
+}
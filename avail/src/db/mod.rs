@@ -1,67 +1,195 @@
+pub mod block_table;
+pub mod bridge_message_table;
+pub mod call_table;
+pub mod checkpoint_table;
+pub mod dead_letter_table;
+pub mod event_table;
 pub mod execute_table;
 pub mod main_table;
+pub mod migrations;
+pub mod quarantine_table;
+pub mod repair;
 pub mod send_message_table;
+pub mod types;
 
-use sqlx::{Pool, Postgres, postgres::PgPoolOptions};
+use crate::configuration::Configuration;
+use avail_rust::H256;
+use sqlx::{Pool, Postgres, Transaction, postgres::PgPoolOptions};
 
+#[derive(Clone)]
 pub struct Database {
 	pub conn: Pool<Postgres>,
 	pub main_table_name: String,
 	pub send_message_table_name: String,
 	pub execute_table_name: String,
+	pub checkpoint_table_name: String,
+	pub event_table_name: String,
+	pub bridge_message_table_name: String,
+	pub block_table_name: String,
+	pub quarantine_table_name: String,
+	pub dead_letter_table_name: String,
+	/// Call tables declared by the registered handlers. Empty until `create_call_tables` is called.
+	pub call_table_names: Vec<String>,
 }
 
 impl Database {
-	pub async fn new(
-		url: &str,
-		main_table_name: String,
-		send_message_table_name: String,
-		execute_table_name: String,
-	) -> Result<Self, String> {
+	/// Connects to the DB and applies all pending migrations.
+	pub async fn from_config(config: &Configuration) -> Result<Self, String> {
 		let conn = PgPoolOptions::new()
 			.max_connections(5)
-			.connect(&url)
+			.connect(&config.db_url)
 			.await
 			.map_err(|x| x.to_string())?;
 
 		let db = Self {
 			conn,
-			main_table_name,
-			send_message_table_name,
-			execute_table_name,
+			main_table_name: config.table_name.clone(),
+			send_message_table_name: config.send_message_table_name.clone(),
+			execute_table_name: config.execute_table_name.clone(),
+			checkpoint_table_name: config.checkpoint_table_name.clone(),
+			event_table_name: config.event_table_name.clone(),
+			bridge_message_table_name: config.bridge_message_table_name.clone(),
+			block_table_name: config.block_table_name.clone(),
+			quarantine_table_name: config.quarantine_table_name.clone(),
+			dead_letter_table_name: config.dead_letter_table_name.clone(),
+			call_table_names: Vec::new(),
 		};
 
-		main_table::MainTable::create_table(&db).await?;
-		execute_table::ExecuteTable::create_table(&db).await?;
-		send_message_table::SendMessageTable::create_table(&db).await?;
-
+		migrations::run(&db).await?;
 		Ok(db)
 	}
 
-	pub async fn insert(&self, data: DataForDatabase) -> Result<(), String> {
-		for entry in data.main_entries {
-			main_table::MainTable::insert(entry, self).await?;
+	/// Creates the call tables that are missing. Rows of these tables are deleted together with their main table
+	/// rows from now on.
+	pub async fn create_call_tables(&mut self, table_names: Vec<String>) -> Result<(), String> {
+		for table_name in &table_names {
+			call_table::CallTable::create(table_name, self).await?;
 		}
 
-		for entry in data.execute_entries {
-			execute_table::ExecuteTable::insert(entry, self).await?;
-		}
+		self.call_table_names = table_names;
+		Ok(())
+	}
+
+	/// Writes all rows of one or more blocks and advances the checkpoint in a single transaction.
+	/// Either everything is stored or nothing is.
+	pub async fn insert(&self, data: &DataForDatabase, checkpoint: checkpoint_table::TableEntry) -> Result<(), String> {
+		let mut tx = self.conn.begin().await.map_err(|e| e.to_string())?;
+		self.write(data, &mut tx).await?;
+		checkpoint_table::CheckpointTable::upsert(checkpoint, self, &mut tx).await?;
+
+		tx.commit().await.map_err(|e| e.to_string())
+	}
+
+	/// Rewrites already indexed blocks and removes rows of extrinsics that are not part of them, in a single
+	/// transaction. The checkpoint is not touched so this can run next to the live indexer.
+	pub async fn repair(&self, data: &DataForDatabase, extra_ids: &[u64]) -> Result<(), String> {
+		let mut tx = self.conn.begin().await.map_err(|e| e.to_string())?;
+		main_table::MainTable::delete_many(extra_ids, self, &mut tx).await?;
+		self.write(data, &mut tx).await?;
 
-		for entry in data.send_message_entries {
-			send_message_table::SendMessageTable::insert(entry, self).await?;
+		tx.commit().await.map_err(|e| e.to_string())
+	}
+
+	async fn write(&self, data: &DataForDatabase, tx: &mut Transaction<'_, Postgres>) -> Result<(), String> {
+		block_table::BlockTable::insert_many(&data.block_entries, self, tx).await?;
+		let indexed_heights: Vec<u32> = data.block_entries.iter().map(|x| x.block_height).collect();
+		quarantine_table::QuarantineTable::delete_many(&indexed_heights, self, tx).await?;
+		quarantine_table::QuarantineTable::insert_many(&data.quarantine_entries, self, tx).await?;
+		dead_letter_table::DeadLetterTable::delete_many(&indexed_heights, self, tx).await?;
+		dead_letter_table::DeadLetterTable::insert_many(&data.dead_letter_entries, self, tx).await?;
+		main_table::MainTable::insert_many(&data.main_entries, self, tx).await?;
+		call_table::CallTable::insert_many(&data.call_entries, self, tx).await?;
+		event_table::EventTable::insert_many(&data.event_entries, self, tx).await?;
+		execute_table::ExecuteTable::insert_many(&data.execute_entries, self, tx).await?;
+		send_message_table::SendMessageTable::insert_many(&data.send_message_entries, self, tx).await?;
+		bridge_message_table::BridgeMessageTable::insert_many(&data.bridge_message_entries, self, tx).await?;
+		if let Some(head) = data.vector_head {
+			bridge_message_table::BridgeMessageTable::advance_on_head_update(head, self, tx).await?;
 		}
 
 		Ok(())
 	}
 
+	/// Upserts several checkpoints in a single transaction.
+	pub async fn insert_checkpoints(&self, values: Vec<checkpoint_table::TableEntry>) -> Result<(), String> {
+		let mut tx = self.conn.begin().await.map_err(|e| e.to_string())?;
+		for value in values {
+			checkpoint_table::CheckpointTable::upsert(value, self, &mut tx).await?;
+		}
+
+		tx.commit().await.map_err(|e| e.to_string())
+	}
+
 	pub async fn find_highest_block_height(&self) -> Result<Option<u32>, String> {
 		main_table::MainTable::find_highest_block_height(self).await
 	}
+
+	/// Heights and hashes of the stored blocks between `from` and `to`, both inclusive.
+	pub async fn find_blocks(&self, from: u32, to: u32) -> Result<Vec<(u32, H256)>, String> {
+		block_table::BlockTable::find_range(from, to, self).await
+	}
+
+	/// Heights and ids of the stored extrinsics between `from` and `to`, both inclusive.
+	pub async fn find_ext_ids(&self, from: u32, to: u32) -> Result<Vec<(u32, u64)>, String> {
+		main_table::MainTable::find_ids(from, to, self).await
+	}
+
+	pub async fn find_unfinished_backfills(&self) -> Result<Vec<(u32, u32)>, String> {
+		checkpoint_table::CheckpointTable::find_unfinished_backfills(self).await
+	}
+
+	pub async fn find_checkpoint(&self, name: &str) -> Result<Option<checkpoint_table::TableEntry>, String> {
+		checkpoint_table::CheckpointTable::find(name, self).await
+	}
 }
 
 #[derive(Default)]
 pub struct DataForDatabase {
+	/// One entry for every processed block, including blocks without any tracked extrinsic
+	pub block_entries: Vec<block_table::TableEntry>,
 	pub main_entries: Vec<main_table::TableEntry>,
+	/// Rows of calls that use the raw decoder
+	pub call_entries: Vec<call_table::TableEntry>,
 	pub execute_entries: Vec<execute_table::TableEntry>,
 	pub send_message_entries: Vec<send_message_table::TableEntry>,
+	pub event_entries: Vec<event_table::TableEntry>,
+	pub bridge_message_entries: Vec<bridge_message_table::TableEntry>,
+	/// Blocks that failed permanently and were skipped
+	pub quarantine_entries: Vec<quarantine_table::TableEntry>,
+	/// Extrinsics that could not be decoded or serialized
+	pub dead_letter_entries: Vec<dead_letter_table::TableEntry>,
+	/// Latest Vector head update
+	pub vector_head: Option<bridge_message_table::VectorHead>,
+}
+
+impl DataForDatabase {
+	pub fn append(&mut self, mut other: DataForDatabase) {
+		self.block_entries.append(&mut other.block_entries);
+		self.main_entries.append(&mut other.main_entries);
+		self.call_entries.append(&mut other.call_entries);
+		self.execute_entries.append(&mut other.execute_entries);
+		self.send_message_entries.append(&mut other.send_message_entries);
+		self.event_entries.append(&mut other.event_entries);
+		self.bridge_message_entries.append(&mut other.bridge_message_entries);
+		self.quarantine_entries.append(&mut other.quarantine_entries);
+		self.dead_letter_entries.append(&mut other.dead_letter_entries);
+		self.vector_head = self.vector_head.max(other.vector_head);
+	}
+}
+
+/// Data of consecutive blocks that has not been written to the DB yet.
+#[derive(Default)]
+pub struct WriteBatch {
+	pub data: DataForDatabase,
+	pub block_count: u32,
+	/// Height and hash of the last block in the batch. This is what the checkpoint will point to.
+	pub last_block: Option<(u32, H256)>,
+}
+
+impl WriteBatch {
+	pub fn push(&mut self, data: DataForDatabase, block_height: u32, block_hash: H256) {
+		self.data.append(data);
+		self.block_count += 1;
+		self.last_block = Some((block_height, block_hash));
+	}
 }
@@ -0,0 +1,118 @@
+use crate::db::{Database, types};
+use avail_rust::H256;
+use sqlx::{
+	Postgres, Transaction,
+	types::chrono::{DateTime, Utc},
+};
+
+pub struct QuarantineTable;
+impl QuarantineTable {
+	/// Upserts all entries with a single statement.
+	pub async fn insert_many(
+		values: &[TableEntry],
+		db: &Database,
+		tx: &mut Transaction<'_, Postgres>,
+	) -> Result<(), String> {
+		if values.is_empty() {
+			return Ok(());
+		}
+
+		let q = std::format!(
+			"
+				INSERT INTO {} (
+					block_height,
+					block_hash,
+					error,
+					attempts,
+					quarantined_at
+				)
+				SELECT * FROM UNNEST(
+					$1::BIGINT[],
+					$2::BYTEA[],
+					$3::TEXT[],
+					$4::BIGINT[],
+					$5::TIMESTAMPTZ[]
+				)
+				ON CONFLICT (block_height) DO UPDATE SET
+					block_hash = EXCLUDED.block_hash,
+					error = EXCLUDED.error,
+					attempts = EXCLUDED.attempts,
+					quarantined_at = EXCLUDED.quarantined_at
+			",
+			db.quarantine_table_name
+		);
+
+		let mut block_heights = Vec::with_capacity(values.len());
+		let mut block_hashes = Vec::with_capacity(values.len());
+		let mut errors = Vec::with_capacity(values.len());
+		let mut attempts = Vec::with_capacity(values.len());
+		let mut quarantined_ats = Vec::with_capacity(values.len());
+		for value in values {
+			block_heights.push(types::encode_u32(value.block_height));
+			block_hashes.push(types::encode_h256(&value.block_hash));
+			errors.push(value.error.clone());
+			attempts.push(types::encode_u32(value.attempts));
+			quarantined_ats.push(value.quarantined_at);
+		}
+
+		let _ = sqlx::query(&q)
+			.bind(block_heights)
+			.bind(block_hashes)
+			.bind(errors)
+			.bind(attempts)
+			.bind(quarantined_ats)
+			.execute(&mut **tx)
+			.await
+			.map_err(|e| e.to_string())?;
+
+		Ok(())
+	}
+
+	/// Removes the records of blocks that have now been indexed successfully.
+	pub async fn delete_many(
+		block_heights: &[u32],
+		db: &Database,
+		tx: &mut Transaction<'_, Postgres>,
+	) -> Result<(), String> {
+		if block_heights.is_empty() {
+			return Ok(());
+		}
+
+		let q = std::format!("DELETE FROM {} WHERE block_height = ANY($1::BIGINT[])", db.quarantine_table_name);
+		let block_heights: Vec<i64> = block_heights.iter().copied().map(types::encode_u32).collect();
+		let _ = sqlx::query(&q)
+			.bind(block_heights)
+			.execute(&mut **tx)
+			.await
+			.map_err(|e| e.to_string())?;
+
+		Ok(())
+	}
+}
+
+/// A block that failed permanently. The indexer moves past it and the block can be indexed later with
+/// `verify --repair`, which removes the record.
+pub struct TableEntry {
+	/// In the DB this is stored as "BIGINT PRIMARY KEY"
+	pub block_height: u32,
+	/// In the DB this is stored as "BYTEA NOT NULL"
+	pub block_hash: H256,
+	/// In the DB this is stored as "TEXT NOT NULL"
+	pub error: String,
+	/// In the DB this is stored as "BIGINT NOT NULL"
+	pub attempts: u32,
+	/// In the DB this is stored as "TIMESTAMPTZ NOT NULL"
+	pub quarantined_at: DateTime<Utc>,
+}
+
+impl TableEntry {
+	pub fn new(block_height: u32, block_hash: H256, error: String, attempts: u32) -> Self {
+		Self {
+			block_height,
+			block_hash,
+			error,
+			attempts,
+			quarantined_at: Utc::now(),
+		}
+	}
+}
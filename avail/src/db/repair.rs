@@ -0,0 +1,126 @@
+//! Finds and fixes rows that were written while unsigned identifiers were bound as signed integers.
+//!
+//! Vector message ids above `i64::MAX` were stored as negative numbers. The JSON encoded call in the main
+//! table was never truncated, so it is used as the source of truth.
+
+use crate::db::Database;
+use sqlx::{Postgres, Row, Transaction};
+
+/// Message id of an Execute call, read from the main table row aliased as `m`
+const CALL_MESSAGE_ID: &str = "(m.ext_call::jsonb -> 'addr_message' ->> 'id')::NUMERIC(78)";
+
+/// Number of rows whose identifiers don't match the values they were derived from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WrappedIds {
+	pub execute_message_ids: u64,
+	pub bridge_message_ids: u64,
+	/// Main table ids that don't match `block_height << 32 | ext_index`. These can't be derived from
+	/// `ext_call` and are only reported.
+	pub main_ids: u64,
+}
+
+impl WrappedIds {
+	pub fn is_empty(&self) -> bool {
+		*self == Self::default()
+	}
+}
+
+/// Rewrites every wrapped execute and bridge message id in a single transaction.
+/// Returns what was found before the rows were rewritten.
+pub async fn repair(db: &Database) -> Result<WrappedIds, String> {
+	let mut tx = db.conn.begin().await.map_err(|e| e.to_string())?;
+	let found = count(db, &mut tx).await?;
+	for statement in statements(db) {
+		sqlx::query(&statement)
+			.execute(&mut *tx)
+			.await
+			.map_err(|e| std::format!("Failed to repair wrapped ids. Error: {}", e.to_string()))?;
+	}
+	tx.commit().await.map_err(|e| e.to_string())?;
+
+	Ok(found)
+}
+
+/// Statements that rewrite wrapped ids from `ext_call`. Migration v9 runs a frozen copy of them; changes only
+/// go here.
+fn statements(db: &Database) -> Vec<String> {
+	let main = &db.main_table_name;
+	let execute = &db.execute_table_name;
+	let bridge_message = &db.bridge_message_table_name;
+	vec![
+		std::format!(
+			"
+				UPDATE {} AS e SET message_id = {}
+				FROM {} AS m
+				WHERE e.id = m.id AND e.message_id <> {};
+			",
+			execute,
+			CALL_MESSAGE_ID,
+			main,
+			CALL_MESSAGE_ID
+		),
+		// The correct row might already exist if the message was seen again after the wrapped one was written.
+		std::format!(
+			"
+				DELETE FROM {} AS b
+				USING {} AS m
+				WHERE b.direction = 'ethereum_to_avail' AND b.execute_ext_id = m.id AND b.message_id <> {}
+					AND EXISTS (SELECT 1 FROM {} AS c WHERE c.direction = b.direction AND c.message_id = {});
+			",
+			bridge_message,
+			main,
+			CALL_MESSAGE_ID,
+			bridge_message,
+			CALL_MESSAGE_ID
+		),
+		std::format!(
+			"
+				UPDATE {} AS b SET message_id = {}, updated_at = NOW()
+				FROM {} AS m
+				WHERE b.direction = 'ethereum_to_avail' AND b.execute_ext_id = m.id AND b.message_id <> {};
+			",
+			bridge_message,
+			CALL_MESSAGE_ID,
+			main,
+			CALL_MESSAGE_ID
+		),
+	]
+}
+
+async fn count(db: &Database, tx: &mut Transaction<'_, Postgres>) -> Result<WrappedIds, String> {
+	let main = &db.main_table_name;
+	let execute_message_ids = std::format!(
+		"SELECT COUNT(*) FROM {} AS e JOIN {} AS m ON m.id = e.id WHERE e.message_id <> {}",
+		db.execute_table_name,
+		main,
+		CALL_MESSAGE_ID
+	);
+	let bridge_message_ids = std::format!(
+		"
+			SELECT COUNT(*) FROM {} AS b JOIN {} AS m ON m.id = b.execute_ext_id
+			WHERE b.direction = 'ethereum_to_avail' AND b.message_id <> {}
+		",
+		db.bridge_message_table_name,
+		main,
+		CALL_MESSAGE_ID
+	);
+	let main_ids = std::format!(
+		"SELECT COUNT(*) FROM {} AS m WHERE m.id <> m.block_height::NUMERIC * 4294967296 + m.ext_index",
+		main
+	);
+
+	Ok(WrappedIds {
+		execute_message_ids: count_rows(&execute_message_ids, tx).await?,
+		bridge_message_ids: count_rows(&bridge_message_ids, tx).await?,
+		main_ids: count_rows(&main_ids, tx).await?,
+	})
+}
+
+async fn count_rows(q: &str, tx: &mut Transaction<'_, Postgres>) -> Result<u64, String> {
+	let row = sqlx::query(q).fetch_one(&mut **tx).await.map_err(|e| e.to_string())?;
+	let value = row
+		.try_get::<i64, _>("count")
+		.map_err(|e| std::format!("Failed to convert count. Error: {}", e.to_string()))?;
+
+	Ok(value as u64)
+}
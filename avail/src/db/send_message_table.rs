@@ -1,51 +1,98 @@
 use avail_rust::H256;
+use sqlx::{Postgres, Transaction};
 
-use crate::{common::SerializedSendMessage, db::Database};
+use crate::{
+	common::SerializedSendMessage,
+	db::{Database, types},
+};
 
 pub struct SendMessageTable;
 impl SendMessageTable {
-	pub async fn create_table(db: &Database) -> Result<(), String> {
-		let q = std::format!(
-			"
-				CREATE TABLE IF NOT EXISTS {} (
-					id BIGINT PRIMARY KEY REFERENCES {},
-					\"type\" TEXT NOT NULL,
-					amount TEXT,
-					\"to\" TEXT NOT NULL
-				);
-			",
-			&db.send_message_table_name,
-			&db.main_table_name
-		);
-
-		sqlx::query(&q).execute(&db.conn).await.map_err(|e| e.to_string())?;
-		Ok(())
-	}
+	/// Upserts all entries with a single statement.
+	pub async fn insert_many(
+		values: &[TableEntry],
+		db: &Database,
+		tx: &mut Transaction<'_, Postgres>,
+	) -> Result<(), String> {
+		if values.is_empty() {
+			return Ok(());
+		}
 
-	pub async fn insert(value: TableEntry, db: &Database) -> Result<(), String> {
 		let q = std::format!(
 			"
 				INSERT INTO {} (
 					id,
 					\"type\",
 					amount,
-					\"to\"
+					\"to\",
+					domain,
+					asset_id,
+					payload,
+					sender,
+					status,
+					status_source
+				)
+				SELECT * FROM UNNEST(
+					$1::TEXT[]::NUMERIC(20)[],
+					$2::TEXT[],
+					$3::TEXT[]::NUMERIC(39)[],
+					$4::BYTEA[],
+					$5::BIGINT[],
+					$6::BYTEA[],
+					$7::TEXT[],
+					$8::TEXT[],
+					$9::TEXT[],
+					$10::TEXT[]
 				)
-				VALUES ($1, $2, $3, $4)
 				ON CONFLICT (id) DO UPDATE SET
 					\"type\" = EXCLUDED.\"type\",
 					amount = EXCLUDED.amount,
-					\"to\" = EXCLUDED.\"to\"
+					\"to\" = EXCLUDED.\"to\",
+					domain = EXCLUDED.domain,
+					asset_id = EXCLUDED.asset_id,
+					payload = EXCLUDED.payload,
+					sender = EXCLUDED.sender,
+					status = EXCLUDED.status,
+					status_source = EXCLUDED.status_source
 			",
 			&db.send_message_table_name
 		);
-		let amount = value.amount.map(|x| std::format!("{}", x));
+
+		let mut ids = Vec::with_capacity(values.len());
+		let mut kinds = Vec::with_capacity(values.len());
+		let mut amounts = Vec::with_capacity(values.len());
+		let mut tos = Vec::with_capacity(values.len());
+		let mut domains = Vec::with_capacity(values.len());
+		let mut asset_ids = Vec::with_capacity(values.len());
+		let mut payloads = Vec::with_capacity(values.len());
+		let mut senders = Vec::with_capacity(values.len());
+		let mut statuses = Vec::with_capacity(values.len());
+		let mut status_sources = Vec::with_capacity(values.len());
+		for value in values {
+			ids.push(types::encode_u64(value.id));
+			kinds.push(value.kind.clone());
+			amounts.push(value.amount.map(types::encode_u128));
+			tos.push(types::encode_h256(&value.to));
+			domains.push(types::encode_u32(value.domain));
+			asset_ids.push(value.asset_id.as_ref().map(types::encode_h256));
+			payloads.push(value.payload.clone());
+			senders.push(value.sender.clone());
+			statuses.push(value.status.as_str());
+			status_sources.push(value.status.source());
+		}
+
 		let _ = sqlx::query(&q)
-			.bind(value.id as i64)
-			.bind(value.kind)
-			.bind(amount)
-			.bind(std::format!("{:?}", value.to))
-			.execute(&db.conn)
+			.bind(ids)
+			.bind(kinds)
+			.bind(amounts)
+			.bind(tos)
+			.bind(domains)
+			.bind(asset_ids)
+			.bind(payloads)
+			.bind(senders)
+			.bind(statuses)
+			.bind(status_sources)
+			.execute(&mut **tx)
 			.await
 			.map_err(|e| e.to_string())?;
 
@@ -54,23 +101,67 @@ impl SendMessageTable {
 }
 
 pub struct TableEntry {
-	/// In the DB this is stored as "BIGINT PRIMARY KEY"
+	/// In the DB this is stored as "NUMERIC(20) PRIMARY KEY"
 	pub id: u64,
 	/// In the DB this is stored as "TEXT NOT NULL"
 	pub kind: String,
-	/// In the DB this is stored as "TEXT"
+	/// In the DB this is stored as "NUMERIC(39)"
 	pub amount: Option<u128>,
-	/// In the DB this is stored as "TEXT NOT NULL"
+	/// In the DB this is stored as "BYTEA NOT NULL"
 	pub to: H256,
+	/// Destination domain
+	/// In the DB this is stored as "BIGINT"
+	pub domain: u32,
+	/// Only set for Fungible Token messages
+	/// In the DB this is stored as "BYTEA"
+	pub asset_id: Option<H256>,
+	/// Hex encoded. Only set for Arbitrary Messages
+	/// In the DB this is stored as "TEXT"
+	pub payload: Option<String>,
+	// ss58 address
+	/// In the DB this is stored as "TEXT"
+	pub sender: Option<String>,
+	/// In the DB this is stored as "TEXT NOT NULL" together with "status_source TEXT"
+	pub status: Status,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+	/// Included in a block and not reported as failed
+	Submitted,
+	/// Its ext index was listed by Vector::FailedSendMessageTxs in the same block
+	FailedByVector,
+}
+
+impl Status {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Status::Submitted => "submitted",
+			Status::FailedByVector => "failed_by_vector",
+		}
+	}
+
+	/// Where the status was taken from, if it is anything other than submitted
+	pub fn source(&self) -> Option<&'static str> {
+		match self {
+			Status::Submitted => None,
+			Status::FailedByVector => Some("Vector::FailedSendMessageTxs"),
+		}
+	}
 }
 
 impl TableEntry {
-	pub fn from_call(id: u64, call: &SerializedSendMessage) -> Self {
+	pub fn from_call(id: u64, call: &SerializedSendMessage, sender: Option<String>) -> Self {
 		Self {
 			id,
 			kind: call.message.kind().to_string(),
 			amount: call.message.amount(),
 			to: call.to,
+			domain: call.domain,
+			asset_id: call.message.asset_id(),
+			payload: call.message.payload(),
+			sender,
+			status: Status::Submitted,
 		}
 	}
 }
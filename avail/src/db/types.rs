@@ -0,0 +1,145 @@
+//! Conversions between Rust values and their DB representation.
+//!
+//! Hashes are stored as "BYTEA" and amounts as "NUMERIC(39)". sqlx can't read "NUMERIC" without extra
+//! dependencies, so numeric columns need to be cast to "TEXT" in the query before they are read.
+//!
+//! Postgres has no unsigned integers. u64 values (ids, slots, message ids) are stored as "NUMERIC(20)" and
+//! u32 values as "BIGINT". Casting them to a signed integer of the same width wraps large values to negative
+//! numbers, so never bind them with `as i64` / `as i32`.
+
+use avail_rust::H256;
+use sqlx::{Row, postgres::PgRow};
+
+pub fn encode_h256(value: &H256) -> Vec<u8> {
+	value.as_bytes().to_vec()
+}
+
+pub fn decode_h256(value: &[u8]) -> Result<H256, String> {
+	if value.len() != 32 {
+		return Err(std::format!("Failed to decode H256. Expected 32 bytes but got {}", value.len()));
+	}
+
+	Ok(H256::from_slice(value))
+}
+
+/// Reads a "BYTEA" column
+pub fn read_h256(row: &PgRow, column: &str) -> Result<H256, String> {
+	let value = row
+		.try_get::<Vec<u8>, _>(column)
+		.map_err(|e| std::format!("Failed to read {}. Error: {}", column, e.to_string()))?;
+	decode_h256(&value)
+}
+
+/// Reads a nullable "BYTEA" column
+pub fn read_optional_h256(row: &PgRow, column: &str) -> Result<Option<H256>, String> {
+	let value = row
+		.try_get::<Option<Vec<u8>>, _>(column)
+		.map_err(|e| std::format!("Failed to read {}. Error: {}", column, e.to_string()))?;
+	value.map(|x| decode_h256(&x)).transpose()
+}
+
+/// Bound as "TEXT" and cast to "NUMERIC" inside the query
+pub fn encode_u128(value: u128) -> String {
+	value.to_string()
+}
+
+pub fn decode_u128(value: &str) -> Result<u128, String> {
+	value
+		.parse::<u128>()
+		.map_err(|e| std::format!("Failed to decode u128. Error: {}", e.to_string()))
+}
+
+/// Reads a "NUMERIC" column that was cast to "TEXT" in the query
+pub fn read_u128(row: &PgRow, column: &str) -> Result<u128, String> {
+	let value = row
+		.try_get::<String, _>(column)
+		.map_err(|e| std::format!("Failed to read {}. Error: {}", column, e.to_string()))?;
+	decode_u128(&value).map_err(|e| std::format!("Failed to parse {}. {}", column, e))
+}
+
+/// Reads a nullable "NUMERIC" column that was cast to "TEXT" in the query
+pub fn read_optional_u128(row: &PgRow, column: &str) -> Result<Option<u128>, String> {
+	let value = row
+		.try_get::<Option<String>, _>(column)
+		.map_err(|e| std::format!("Failed to read {}. Error: {}", column, e.to_string()))?;
+	value
+		.map(|x| decode_u128(&x))
+		.transpose()
+		.map_err(|e| std::format!("Failed to parse {}. {}", column, e))
+}
+
+/// Bound as "TEXT" and cast to "NUMERIC(20)" inside the query
+pub fn encode_u64(value: u64) -> String {
+	value.to_string()
+}
+
+pub fn decode_u64(value: &str) -> Result<u64, String> {
+	value
+		.parse::<u64>()
+		.map_err(|e| std::format!("Failed to decode u64. Error: {}", e.to_string()))
+}
+
+/// Reads a "NUMERIC(20)" column that was cast to "TEXT" in the query
+pub fn read_u64(row: &PgRow, column: &str) -> Result<u64, String> {
+	let value = row
+		.try_get::<String, _>(column)
+		.map_err(|e| std::format!("Failed to read {}. Error: {}", column, e.to_string()))?;
+	decode_u64(&value).map_err(|e| std::format!("Failed to parse {}. {}", column, e))
+}
+
+/// Bound as "BIGINT". Every u32 fits without wrapping.
+pub fn encode_u32(value: u32) -> i64 {
+	value as i64
+}
+
+/// Reads a "BIGINT" column that holds a u32
+pub fn read_u32(row: &PgRow, column: &str) -> Result<u32, String> {
+	let value = row
+		.try_get::<i64, _>(column)
+		.map_err(|e| std::format!("Failed to read {}. Error: {}", column, e.to_string()))?;
+	u32::try_from(value).map_err(|e| std::format!("Failed to convert {} to u32. Error: {}", column, e.to_string()))
+}
+
+/// Reads a nullable "BIGINT" column that holds a u32
+pub fn read_optional_u32(row: &PgRow, column: &str) -> Result<Option<u32>, String> {
+	let value = row
+		.try_get::<Option<i64>, _>(column)
+		.map_err(|e| std::format!("Failed to read {}. Error: {}", column, e.to_string()))?;
+	value
+		.map(u32::try_from)
+		.transpose()
+		.map_err(|e| std::format!("Failed to convert {} to u32. Error: {}", column, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn u128_round_trip() {
+		for value in [0, 1, u64::MAX as u128 + 1, u128::MAX] {
+			assert_eq!(decode_u128(&encode_u128(value)), Ok(value));
+		}
+	}
+
+	#[test]
+	fn u64_round_trip() {
+		for value in [0, 1, i64::MAX as u64 + 1, u64::MAX] {
+			assert_eq!(decode_u64(&encode_u64(value)), Ok(value));
+		}
+		assert!(decode_u64(&encode_u128(u64::MAX as u128 + 1)).is_err());
+	}
+
+	#[test]
+	fn h256_round_trip() {
+		for value in [H256::zero(), H256::repeat_byte(0xff), H256::from_low_u64_be(42)] {
+			assert_eq!(decode_h256(&encode_h256(&value)), Ok(value));
+		}
+		assert!(decode_h256(&[0u8; 31]).is_err());
+	}
+
+	#[test]
+	fn u32_fits_bigint() {
+		assert_eq!(encode_u32(u32::MAX), u32::MAX as i64);
+	}
+}
@@ -0,0 +1,293 @@
+//! Shared RPC clients and health tracking for the configured endpoints.
+//!
+//! Every endpoint keeps a fixed number of clients that are connected once and shared by all tasks. A fetch
+//! borrows a client for its duration, so scaling the number of tasks never opens a connection. Endpoints that
+//! could not be connected to are retried in the background.
+//!
+//! Every fetch reports whether its endpoint answered. After `FAILURE_THRESHOLD` failures in a row the circuit of
+//! that endpoint opens and no fetch uses it. Once the cool-down has passed it is used again; the first answer
+//! either closes the circuit or opens it again for longer. Fetches are spread across the available endpoints
+//! according to their weights.
+
+use crate::{
+	backoff::{self, Backoff},
+	configuration::AvailEndpoint,
+};
+use avail_rust::Client;
+use std::{
+	sync::{
+		Arc, Mutex, Weak,
+		atomic::{AtomicUsize, Ordering},
+	},
+	time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+/// Failures in a row after which an endpoint is taken out of rotation
+const FAILURE_THRESHOLD: u32 = 5;
+/// How long the circuit stays open. Grows every time it opens again without a success in between.
+const CIRCUIT_BACKOFF: Backoff = Backoff::new(Duration::from_secs(10), Duration::from_secs(300));
+/// How often missing clients are connected again
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(30);
+
+pub struct EndpointPool {
+	endpoints: Vec<Endpoint>,
+	/// Clients connected to each endpoint
+	connection_count: usize,
+}
+
+struct Endpoint {
+	url: String,
+	weight: u32,
+	health: Mutex<Health>,
+	clients: Mutex<Vec<Client>>,
+	/// Round robin position among the clients
+	next_client: AtomicUsize,
+}
+
+#[derive(Default)]
+struct Health {
+	consecutive_failures: u32,
+	/// How many times the circuit opened since the last success
+	open_count: u32,
+	/// The circuit is open until then
+	open_until: Option<Instant>,
+}
+
+impl Health {
+	fn is_open(&self) -> bool {
+		self.open_until.is_some_and(|x| Instant::now() < x)
+	}
+}
+
+impl EndpointPool {
+	/// Connects `connection_count` clients to every endpoint. Fails only if no endpoint can be connected to.
+	/// Missing clients are connected in the background.
+	pub async fn connect(endpoints: &[AvailEndpoint], connection_count: u32) -> Result<Arc<Self>, String> {
+		let pool = Arc::new(Self::new(endpoints, connection_count));
+		if let Err(err) = pool.connect_missing().await {
+			return Err(std::format!("Failed to establish a connection with any avail node. Reason: {}", err));
+		}
+
+		tokio::spawn(reconnect(Arc::downgrade(&pool)));
+		Ok(pool)
+	}
+
+	/// Pool without any client. Clients are added by `connect_missing`.
+	pub(crate) fn new(endpoints: &[AvailEndpoint], connection_count: u32) -> Self {
+		let endpoints = endpoints
+			.iter()
+			.map(|x| Endpoint {
+				url: x.url.clone(),
+				weight: x.weight.max(1),
+				health: Mutex::new(Health::default()),
+				clients: Mutex::new(Vec::new()),
+				next_client: AtomicUsize::new(0),
+			})
+			.collect();
+		Self {
+			endpoints,
+			connection_count: connection_count.max(1) as usize,
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.endpoints.len()
+	}
+
+	pub fn url(&self, index: usize) -> &str {
+		&self.endpoints[index].url
+	}
+
+	/// False while the circuit of the endpoint is open
+	pub fn is_available(&self, index: usize) -> bool {
+		!self.health(index).is_open()
+	}
+
+	/// Records whether the endpoint answered.
+	pub fn report(&self, index: usize, success: bool) {
+		let url = self.url(index);
+		let mut health = self.health(index);
+		if success {
+			if health.open_count > 0 {
+				info!(url, "Endpoint recovered. Circuit closed");
+			}
+			*health = Health::default();
+			return;
+		}
+
+		health.consecutive_failures += 1;
+		if health.consecutive_failures >= FAILURE_THRESHOLD && !health.is_open() {
+			health.open_count += 1;
+			let cool_down = CIRCUIT_BACKOFF.delay(health.open_count);
+			health.open_until = Some(Instant::now() + cool_down);
+			warn!(
+				url,
+				consecutive_failures = health.consecutive_failures,
+				cool_down_secs = cool_down.as_secs(),
+				"Endpoint keeps failing. Circuit opened"
+			);
+		}
+	}
+
+	/// Borrows a client of an endpoint picked by weight among the available ones. If every circuit is open, the
+	/// endpoint whose circuit closes first is used anyway. Fails if no endpoint has a connected client.
+	pub fn client(&self) -> Result<(usize, Client), String> {
+		self.candidates()
+			.into_iter()
+			.find_map(|index| Some((index, self.client_of(index)?)))
+			.ok_or_else(|| String::from("No endpoint has a connected client"))
+	}
+
+	/// Borrows a client of the given endpoint. None if none is connected yet.
+	pub fn client_of(&self, index: usize) -> Option<Client> {
+		let endpoint = &self.endpoints[index];
+		let clients = endpoint.clients.lock().unwrap_or_else(|x| x.into_inner());
+		if clients.is_empty() {
+			return None;
+		}
+
+		let position = endpoint.next_client.fetch_add(1, Ordering::Relaxed) % clients.len();
+		Some(clients[position].clone())
+	}
+
+	/// Connects the clients that are still missing. Fails if no endpoint has a client afterwards.
+	async fn connect_missing(&self) -> Result<(), String> {
+		let mut last_error = String::from("No endpoint configured");
+		for (index, endpoint) in self.endpoints.iter().enumerate() {
+			let missing = self.connection_count.saturating_sub(self.client_count(index));
+			for _ in 0..missing {
+				match Client::new(&endpoint.url).await {
+					Ok(client) => endpoint.clients.lock().unwrap_or_else(|x| x.into_inner()).push(client),
+					Err(err) => {
+						last_error = err.to_string();
+						warn!(url = endpoint.url, error = last_error, "Failed to connect to endpoint");
+						self.report(index, false);
+						break;
+					},
+				}
+			}
+		}
+
+		if (0..self.len()).all(|x| self.client_count(x) == 0) {
+			return Err(last_error);
+		}
+
+		Ok(())
+	}
+
+	fn client_count(&self, index: usize) -> usize {
+		self.endpoints[index]
+			.clients
+			.lock()
+			.unwrap_or_else(|x| x.into_inner())
+			.len()
+	}
+
+	/// Available endpoints in weighted random order, followed by the unavailable ones ordered by when their
+	/// circuit closes.
+	fn candidates(&self) -> Vec<usize> {
+		let (mut available, mut unavailable): (Vec<usize>, Vec<usize>) =
+			(0..self.len()).partition(|x| self.is_available(*x));
+
+		let mut candidates = Vec::with_capacity(self.len());
+		while !available.is_empty() {
+			let total_weight: u64 = available.iter().map(|x| self.endpoints[*x].weight as u64).sum();
+			let mut pick = backoff::random_u64() % total_weight;
+			let position = available
+				.iter()
+				.position(|x| {
+					let weight = self.endpoints[*x].weight as u64;
+					let is_picked = pick < weight;
+					pick = pick.saturating_sub(weight);
+					is_picked
+				})
+				.unwrap_or(0);
+			candidates.push(available.remove(position));
+		}
+
+		unavailable.sort_by_key(|x| self.health(*x).open_until);
+		candidates.append(&mut unavailable);
+		candidates
+	}
+
+	fn health(&self, index: usize) -> std::sync::MutexGuard<'_, Health> {
+		// A panic while holding the lock can't leave the health in an invalid state.
+		self.endpoints[index].health.lock().unwrap_or_else(|x| x.into_inner())
+	}
+}
+
+/// Connects missing clients until the pool is dropped.
+async fn reconnect(pool: Weak<EndpointPool>) {
+	loop {
+		tokio::time::sleep(RECONNECT_INTERVAL).await;
+		let Some(pool) = pool.upgrade() else {
+			return;
+		};
+		let _ = pool.connect_missing().await;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn pool() -> EndpointPool {
+		let endpoints = [
+			AvailEndpoint { url: String::from("ws://a"), weight: 1 },
+			AvailEndpoint { url: String::from("ws://b"), weight: 1 },
+		];
+		EndpointPool::new(&endpoints, 1)
+	}
+
+	#[test]
+	fn report_opens_circuit_after_threshold() {
+		let pool = pool();
+		for _ in 0..FAILURE_THRESHOLD - 1 {
+			pool.report(0, false);
+		}
+		assert!(pool.is_available(0));
+
+		pool.report(0, false);
+		assert!(!pool.is_available(0));
+		assert!(pool.is_available(1));
+	}
+
+	#[test]
+	fn report_success_resets_failures() {
+		let pool = pool();
+		for _ in 0..FAILURE_THRESHOLD - 1 {
+			pool.report(0, false);
+		}
+		pool.report(0, true);
+		pool.report(0, false);
+		assert!(pool.is_available(0));
+	}
+
+	#[test]
+	fn report_success_closes_circuit() {
+		let pool = pool();
+		for _ in 0..FAILURE_THRESHOLD {
+			pool.report(0, false);
+		}
+		assert!(!pool.is_available(0));
+
+		pool.report(0, true);
+		assert!(pool.is_available(0));
+		assert_eq!(pool.health(0).open_count, 0);
+	}
+
+	#[test]
+	fn candidates_put_open_circuits_last() {
+		let pool = pool();
+		for _ in 0..FAILURE_THRESHOLD {
+			pool.report(0, false);
+		}
+		assert_eq!(pool.candidates(), vec![1, 0]);
+	}
+
+	#[test]
+	fn client_fails_without_connected_clients() {
+		assert!(pool().client().is_err());
+	}
+}
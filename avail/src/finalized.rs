@@ -0,0 +1,167 @@
+//! Keeps track of the finalized head in the background.
+//!
+//! New finalized heads are received through a block subscription, so the indexers can pick up a block a moment
+//! after it is finalized. If the subscription fails the finalized head is polled instead until subscribing works
+//! again. All indexers of a process share one follower.
+//!
+//! With more than one endpoint every new head is cross-checked: the other available endpoints are asked for their
+//! finalized height and the second highest answer is published, so a single node can't make the indexer run
+//! ahead of finality. Endpoints that drift too far from it are reported as failing.
+
+use crate::endpoints::EndpointPool;
+use avail_rust::subscription::Sub;
+use std::{
+	sync::Arc,
+	time::{Duration, Instant},
+};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// How often the finalized head is polled while the subscription is down
+const POLL_INTERVAL: Duration = Duration::from_secs(20);
+/// How long the follower polls before it tries to subscribe again
+const RESUBSCRIBE_AFTER: Duration = Duration::from_secs(120);
+/// How many blocks an endpoint's finalized height may differ from the published one
+const MAX_FINALIZED_DRIFT: u32 = 10;
+
+struct Follower {
+	endpoints: Arc<EndpointPool>,
+	sender: watch::Sender<u32>,
+}
+
+/// Fetches the current finalized height and starts following it. The receiver always holds the highest
+/// finalized height seen so far.
+pub async fn follow(endpoints: Arc<EndpointPool>) -> Result<watch::Receiver<u32>, String> {
+	let (index, _) = endpoints.client()?;
+	let (sender, receiver) = watch::channel(0);
+	let follower = Follower { endpoints, sender };
+	let finalized_height = follower.fetch_finalized_height(index).await?;
+	let finalized_height = follower.cross_check(index, finalized_height).await;
+	follower.update(finalized_height);
+
+	tokio::spawn(follower.run());
+	Ok(receiver)
+}
+
+impl Follower {
+	async fn run(self) {
+		loop {
+			if let Err(err) = self.subscribe().await {
+				warn!(
+					error = err,
+					poll_interval_secs = POLL_INTERVAL.as_secs(),
+					"Finalized head subscription failed. Polling until it can be restarted."
+				);
+			}
+
+			// Every indexer is gone.
+			if self.sender.is_closed() {
+				return;
+			}
+
+			let poll_until = Instant::now() + RESUBSCRIBE_AFTER;
+			while Instant::now() < poll_until && !self.sender.is_closed() {
+				if let Err(err) = self.poll().await {
+					warn!(error = err, "Failed to poll finalized head");
+				}
+				tokio::time::sleep(POLL_INTERVAL).await;
+			}
+		}
+	}
+
+	/// Forwards finalized heads until the subscription fails, its endpoint is taken out of rotation or nobody
+	/// listens anymore.
+	async fn subscribe(&self) -> Result<(), String> {
+		let (index, node) = self.endpoints.client()?;
+		let from = self.sender.borrow().saturating_add(1);
+		let mut sub = Sub::new(node);
+		sub.use_best_block(false);
+		sub.set_retry_on_error(Some(false));
+		sub.set_block_height(from);
+		info!(from, url = self.endpoints.url(index), "Subscribed to finalized heads");
+
+		while !self.sender.is_closed() {
+			let block = match sub.next().await {
+				Ok(x) => x,
+				Err(err) => {
+					self.endpoints.report(index, false);
+					return Err(err.to_string());
+				},
+			};
+			self.endpoints.report(index, true);
+
+			let finalized_height = self.cross_check(index, block.height).await;
+			self.update(finalized_height);
+
+			if !self.endpoints.is_available(index) {
+				return Err(std::format!("Endpoint {} was taken out of rotation", self.endpoints.url(index)));
+			}
+		}
+
+		Ok(())
+	}
+
+	async fn poll(&self) -> Result<(), String> {
+		let (index, _) = self.endpoints.client()?;
+		let finalized_height = self.fetch_finalized_height(index).await?;
+		let finalized_height = self.cross_check(index, finalized_height).await;
+		self.update(finalized_height);
+		Ok(())
+	}
+
+	/// Asks the other available endpoints for their finalized height and returns the second highest of all
+	/// answers. With nobody to compare against `finalized_height` is returned as is.
+	async fn cross_check(&self, index: usize, finalized_height: u32) -> u32 {
+		let mut heights = vec![(index, finalized_height)];
+		for other in 0..self.endpoints.len() {
+			if other == index || !self.endpoints.is_available(other) {
+				continue;
+			}
+
+			match self.fetch_finalized_height(other).await {
+				Ok(height) => heights.push((other, height)),
+				Err(err) => warn!(url = self.endpoints.url(other), error = err, "Failed to fetch finalized height"),
+			}
+		}
+
+		if heights.len() < 2 {
+			return finalized_height;
+		}
+
+		heights.sort_by_key(|x| std::cmp::Reverse(x.1));
+		let agreed_height = heights[1].1;
+		for (endpoint, height) in heights {
+			if height.abs_diff(agreed_height) > MAX_FINALIZED_DRIFT {
+				warn!(
+					url = self.endpoints.url(endpoint),
+					finalized_height = height,
+					agreed_height,
+					"Endpoint disagrees with the others about the finalized height"
+				);
+				self.endpoints.report(endpoint, false);
+			}
+		}
+
+		agreed_height
+	}
+
+	async fn fetch_finalized_height(&self, index: usize) -> Result<u32, String> {
+		let Some(node) = self.endpoints.client_of(index) else {
+			return Err(std::format!("Not connected to {} yet", self.endpoints.url(index)));
+		};
+
+		let result = node.finalized().block_height().await.map_err(|e| e.to_string());
+		self.endpoints.report(index, result.is_ok());
+		result
+	}
+
+	fn update(&self, block_height: u32) {
+		self.sender.send_if_modified(|current| {
+			let is_newer = block_height > *current;
+			if is_newer {
+				*current = block_height;
+			}
+			is_newer
+		});
+	}
+}
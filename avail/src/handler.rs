@@ -0,0 +1,361 @@
+//! Pluggable extrinsic processors.
+//!
+//! A handler names the calls it processes, decodes them and adds their rows to the write set of the block. Send
+//! Message, Execute, the Vector head updates and the `raw` decoder are built-in handlers. Other crates implement
+//! `ExtrinsicHandler` for their own calls and register the handler with the `Indexer` or the `CallRegistry`.
+//!
+//! Rows are only kept if both `decode` and `contribute` succeed. Otherwise the extrinsic ends up in the dead
+//! letter table and the rest of the block is indexed as usual.
+//!
+//! Handlers write to the tables of `DataForDatabase`. Call table rows may only go to the tables the handler
+//! declares in `call_tables`, as only those are created on startup and cleaned up when a block is rewritten.
+
+use crate::{
+	common::{SerializedExecute, SerializedSendMessage, find_event},
+	db,
+};
+use avail_rust::{
+	HasHeader,
+	avail::vector::{
+		events::{HeadUpdated, MessageSubmitted},
+		tx::{Execute, Fulfill, SendMessage},
+	},
+	block::{BlockEncodedExtrinsic, BlockEvents},
+	ext::{codec::Decode, const_hex},
+};
+use tracing::{info, warn};
+
+pub use crate::db::{
+	DataForDatabase, call_table::TableEntry as CallTableEntry, event_table::TableEntry as EventTableEntry,
+	main_table::TableEntry as MainTableEntry,
+};
+
+pub trait ExtrinsicHandler: Send + Sync + 'static {
+	/// Decoded call that is handed from `decode` to `contribute`
+	type Call;
+
+	/// Used in logs and dead letter errors
+	fn name(&self) -> &str;
+
+	/// (pallet_id, variant_id) of every call this handler processes
+	fn filter_keys(&self) -> Vec<(u8, u8)>;
+
+	/// Call tables that `contribute` writes to
+	fn call_tables(&self) -> Vec<String> {
+		Vec::new()
+	}
+
+	fn decode(&self, ext: &BlockEncodedExtrinsic) -> Result<Self::Call, String>;
+
+	/// Adds the rows of the extrinsic to `write_set`. The main table row is only stored if the handler asks for it
+	/// with `ExtrinsicContext::store`.
+	fn contribute(
+		&self,
+		call: Self::Call,
+		ctx: ExtrinsicContext<'_>,
+		write_set: &mut DataForDatabase,
+	) -> Result<(), String>;
+}
+
+/// Everything known about an extrinsic apart from its call
+pub struct ExtrinsicContext<'a> {
+	pub ext: &'a BlockEncodedExtrinsic,
+	/// Events emitted by the extrinsic
+	pub events: &'a BlockEvents,
+	/// Indices of the Send Message extrinsics that Vector reported as failed in this block. None if the block has
+	/// no Vector::FailedSendMessageTxs extrinsic.
+	pub failed_txs: Option<&'a [u32]>,
+	/// Main table row of the extrinsic. `ext_call` is empty.
+	pub main_entry: db::main_table::TableEntry,
+	/// Event table rows of the extrinsic. They reference the main table row so they are stored together with it.
+	pub event_entries: Vec<db::event_table::TableEntry>,
+}
+
+impl ExtrinsicContext<'_> {
+	/// Stores the main table row with `ext_call` as its JSON encoded call, together with the events.
+	pub fn store(mut self, ext_call: String, write_set: &mut DataForDatabase) {
+		self.main_entry.ext_call = ext_call;
+		write_set.main_entries.push(self.main_entry);
+		write_set.event_entries.extend(self.event_entries);
+	}
+}
+
+/// Object safe counterpart of `ExtrinsicHandler` used by the registry
+pub(crate) trait ProcessExtrinsic: Send + Sync {
+	fn call_tables(&self) -> Vec<String>;
+
+	fn process(&self, ctx: ExtrinsicContext<'_>, write_set: &mut DataForDatabase) -> Result<(), String>;
+}
+
+impl<T: ExtrinsicHandler> ProcessExtrinsic for T {
+	fn call_tables(&self) -> Vec<String> {
+		ExtrinsicHandler::call_tables(self)
+	}
+
+	fn process(&self, ctx: ExtrinsicContext<'_>, write_set: &mut DataForDatabase) -> Result<(), String> {
+		let call = self.decode(ctx.ext)?;
+		// A handler that fails halfway must not leave half of its rows behind.
+		let mut rows = DataForDatabase::default();
+		self.contribute(call, ctx, &mut rows)?;
+
+		let call_tables = ExtrinsicHandler::call_tables(self);
+		if let Some(entry) = rows.call_entries.iter().find(|x| !call_tables.contains(&x.table_name)) {
+			return Err(std::format!("Handler wrote to undeclared call table: {}", entry.table_name));
+		}
+
+		write_set.append(rows);
+		Ok(())
+	}
+}
+
+/// Send Message Table and Bridge Message Table
+#[derive(Debug, Clone)]
+pub struct SendMessageHandler {
+	pub name: String,
+	pub key: (u8, u8),
+}
+
+impl Default for SendMessageHandler {
+	fn default() -> Self {
+		Self {
+			name: "Vector::send_message".to_string(),
+			key: SendMessage::HEADER_INDEX,
+		}
+	}
+}
+
+impl ExtrinsicHandler for SendMessageHandler {
+	type Call = SendMessage;
+
+	fn name(&self) -> &str {
+		&self.name
+	}
+
+	fn filter_keys(&self) -> Vec<(u8, u8)> {
+		vec![self.key]
+	}
+
+	fn decode(&self, ext: &BlockEncodedExtrinsic) -> Result<SendMessage, String> {
+		decode_call_body::<SendMessage>(&ext.call)
+			.map_err(|e| std::format!("Failed to decode call as Send Message. Error: {}", e.to_string()))
+	}
+
+	fn contribute(
+		&self,
+		call: SendMessage,
+		ctx: ExtrinsicContext<'_>,
+		write_set: &mut DataForDatabase,
+	) -> Result<(), String> {
+		let Some(failed_txs) = ctx.failed_txs else {
+			return Err(String::from("Block has no Vector::FailedSendMessageTxs extrinsic"));
+		};
+		let main_entry = &ctx.main_entry;
+		let (block_height, extrinsic_index, id) = (main_entry.block_height, main_entry.ext_index, main_entry.id);
+		let serialized_call = SerializedSendMessage::from(call);
+		let ext_call = serialized_call.to_json()?;
+
+		let mut extra_entry =
+			db::send_message_table::TableEntry::from_call(id, &serialized_call, main_entry.signature_address.clone());
+
+		if failed_txs.contains(&extrinsic_index) {
+			warn!(
+				block_height,
+				extrinsic_index, "✉️  Send Message found but its ext index is in failed txs list. Storing it as failed",
+			);
+			extra_entry.status = db::send_message_table::Status::FailedByVector;
+		} else {
+			info!(block_height, extrinsic_index, "✉️  Send Message",);
+		}
+
+		let is_sent =
+			extra_entry.status == db::send_message_table::Status::Submitted && main_entry.ext_success != Some(false);
+		if is_sent {
+			match find_event::<MessageSubmitted>(ctx.events) {
+				Some(event) => write_set
+					.bridge_message_entries
+					.push(db::bridge_message_table::TableEntry::initiated(event.message_id, id)),
+				None => warn!(block_height, extrinsic_index, "✉️  Send Message without MessageSubmitted event"),
+			}
+		}
+
+		ctx.store(ext_call, write_set);
+		write_set.send_message_entries.push(extra_entry);
+		Ok(())
+	}
+}
+
+/// Execute Table and Bridge Message Table
+#[derive(Debug, Clone)]
+pub struct ExecuteHandler {
+	pub name: String,
+	pub key: (u8, u8),
+}
+
+impl Default for ExecuteHandler {
+	fn default() -> Self {
+		Self {
+			name: "Vector::execute".to_string(),
+			key: Execute::HEADER_INDEX,
+		}
+	}
+}
+
+impl ExtrinsicHandler for ExecuteHandler {
+	type Call = Execute;
+
+	fn name(&self) -> &str {
+		&self.name
+	}
+
+	fn filter_keys(&self) -> Vec<(u8, u8)> {
+		vec![self.key]
+	}
+
+	fn decode(&self, ext: &BlockEncodedExtrinsic) -> Result<Execute, String> {
+		decode_call_body::<Execute>(&ext.call)
+			.map_err(|e| std::format!("Failed to decode call as Execute. Error: {}", e.to_string()))
+	}
+
+	fn contribute(
+		&self,
+		call: Execute,
+		ctx: ExtrinsicContext<'_>,
+		write_set: &mut DataForDatabase,
+	) -> Result<(), String> {
+		let main_entry = &ctx.main_entry;
+		info!(block_height = main_entry.block_height, extrinsic_index = main_entry.ext_index, "☠️  Execute",);
+		let serialized_call = SerializedExecute::from(call);
+		let ext_call = serialized_call.to_json()?;
+
+		let id = main_entry.id;
+		let extra_entry = db::execute_table::TableEntry::from_call(id, &serialized_call, main_entry.ext_success);
+
+		// Without events it is unknown whether the message was executed, so it is only known to be claimable.
+		let (message_id, slot) = (extra_entry.message_id, extra_entry.slot);
+		let bridge_entry = match main_entry.ext_success {
+			Some(true) => db::bridge_message_table::TableEntry::bridged(message_id, slot, id),
+			Some(false) | None => db::bridge_message_table::TableEntry::claim_ready(message_id, slot),
+		};
+		write_set.bridge_message_entries.push(bridge_entry);
+
+		ctx.store(ext_call, write_set);
+		write_set.execute_entries.push(extra_entry);
+		Ok(())
+	}
+}
+
+/// Decodes a call without looking at its (pallet, variant) header. The registry already routed the call here by
+/// its header, which may be a different one than `T` was built with if a runtime upgrade moved the call.
+fn decode_call_body<T: Decode>(call: &[u8]) -> Result<T, String> {
+	let mut body = call
+		.get(2..)
+		.ok_or_else(|| String::from("Call is shorter than its header"))?;
+	let value = T::decode(&mut body).map_err(|e| e.to_string())?;
+	if !body.is_empty() {
+		return Err(std::format!("{} bytes left after decoding the call", body.len()));
+	}
+
+	Ok(value)
+}
+
+/// Vector head updates. They are only used to move bridge messages forward, so nothing is stored for the call
+/// itself.
+#[derive(Debug, Clone)]
+pub struct VectorFulfillHandler {
+	pub name: String,
+	pub key: (u8, u8),
+}
+
+impl Default for VectorFulfillHandler {
+	fn default() -> Self {
+		Self {
+			name: "Vector::fulfill".to_string(),
+			key: Fulfill::HEADER_INDEX,
+		}
+	}
+}
+
+impl ExtrinsicHandler for VectorFulfillHandler {
+	// Only the events are of interest.
+	type Call = ();
+
+	fn name(&self) -> &str {
+		&self.name
+	}
+
+	fn filter_keys(&self) -> Vec<(u8, u8)> {
+		vec![self.key]
+	}
+
+	fn decode(&self, _ext: &BlockEncodedExtrinsic) -> Result<(), String> {
+		Ok(())
+	}
+
+	fn contribute(&self, _call: (), ctx: ExtrinsicContext<'_>, write_set: &mut DataForDatabase) -> Result<(), String> {
+		if let Some(head) = find_event::<HeadUpdated>(ctx.events) {
+			let (block_height, extrinsic_index) = (ctx.main_entry.block_height, ctx.main_entry.ext_index);
+			info!(block_height, extrinsic_index, slot = head.slot, "🗣️  Vector Head Update");
+			let head = db::bridge_message_table::VectorHead { ext_id: ctx.main_entry.id, slot: head.slot };
+			write_set.vector_head = write_set.vector_head.max(Some(head));
+		}
+
+		Ok(())
+	}
+}
+
+/// Calls without a decoder of their own. The main table gets the hex encoded call, `table_name` the raw bytes.
+#[derive(Debug, Clone)]
+pub struct RawCallHandler {
+	pub name: String,
+	pub key: (u8, u8),
+	pub table_name: String,
+}
+
+impl ExtrinsicHandler for RawCallHandler {
+	type Call = Vec<u8>;
+
+	fn name(&self) -> &str {
+		&self.name
+	}
+
+	fn filter_keys(&self) -> Vec<(u8, u8)> {
+		vec![self.key]
+	}
+
+	fn call_tables(&self) -> Vec<String> {
+		vec![self.table_name.clone()]
+	}
+
+	fn decode(&self, ext: &BlockEncodedExtrinsic) -> Result<Vec<u8>, String> {
+		Ok(ext.call.clone())
+	}
+
+	fn contribute(
+		&self,
+		call: Vec<u8>,
+		ctx: ExtrinsicContext<'_>,
+		write_set: &mut DataForDatabase,
+	) -> Result<(), String> {
+		let main_entry = &ctx.main_entry;
+		info!(
+			block_height = main_entry.block_height,
+			extrinsic_index = main_entry.ext_index,
+			call = self.name,
+			"📦 Tracked call",
+		);
+		let ext_call = serde_json::Value::String(const_hex::encode_prefixed(&call)).to_string();
+
+		let call_entry = db::call_table::TableEntry {
+			table_name: self.table_name.clone(),
+			id: main_entry.id,
+			block_height: main_entry.block_height,
+			pallet_id: main_entry.pallet_id,
+			variant_id: main_entry.variant_id,
+			call,
+		};
+
+		ctx.store(ext_call, write_set);
+		write_set.call_entries.push(call_entry);
+		Ok(())
+	}
+}
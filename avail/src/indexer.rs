@@ -1,250 +1,473 @@
 use crate::{
-	common::{convert_extrinsics_to_table_entries, fetch_block_timestamp_and_failed_txs},
+	common::{INHERENTS, convert_extrinsics_to_table_entries, read_block_inherents},
+	concurrency::ConcurrencyController,
 	configuration::Configuration,
-	db::{DataForDatabase, Database},
+	db::{DataForDatabase, Database, WriteBatch, block_table, checkpoint_table, dead_letter_table},
+	endpoints::EndpointPool,
+	finalized,
+	handler::ExtrinsicHandler,
+	pipeline::{Failure, Pipeline},
+	registry::CallRegistry,
 	stats::IndexerStats,
 };
 use avail_rust::{
-	Client, HasHeader,
-	avail::vector::tx::{Execute, SendMessage},
-	block::extrinsic_options::Options,
+	Client, H256,
+	block::{BlockEncodedExtrinsic, extrinsic_options::Options},
 };
-use std::time::{Duration, Instant};
-use tokio::task::JoinHandle;
-use tracing::{error as terror, info};
+use std::{
+	sync::Arc,
+	time::{Duration, Instant},
+};
+use tokio::{
+	sync::watch,
+	task::{JoinHandle, JoinSet},
+};
+use tracing::{error as terror, info, warn};
 
 const SLEEP_DURATION_ON_ERROR: Duration = Duration::from_secs(30);
+/// How many heights each task may fetch ahead of the lowest unfinished one
+const WINDOW_PER_TASK: u32 = 4;
+/// Upper bound on how long the main loop waits for a block, so the batch is still flushed on time
+const PIPELINE_WAIT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Which blocks an indexer is responsible for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+	/// Follows the finalized head forever and advances the live indexer checkpoint.
+	Live,
+	/// Indexes `from..=to` (both inclusive) and stops once every block is committed. It advances its own
+	/// checkpoint so an interrupted run resumes where it stopped.
+	Backfill { from: u32, to: u32 },
+}
+
+/// Connections shared by every indexer of a process
+#[derive(Clone)]
+pub(crate) struct Connections {
+	pub db: Database,
+	pub endpoints: Arc<EndpointPool>,
+	/// Updated in the background whenever a new block is finalized
+	pub finalized_heads: watch::Receiver<u32>,
+}
 
 pub struct Indexer {
-	node: Client,
 	db: Database,
+	endpoints: Arc<EndpointPool>,
 	config: Configuration,
+	checkpoint_name: String,
 	next_height_to_index: u32,
+	/// Last height to index. None means forever.
+	end_height: Option<u32>,
 	finalized_height: u32,
+	finalized_heads: watch::Receiver<u32>,
 	stats: IndexerStats,
-	filter: Options,
+	registry: Arc<CallRegistry>,
+	batch: WriteBatch,
+	last_flush: Instant,
 }
 
 impl Indexer {
-	/// Creates DB and Node instance. Calculates start height.
-	pub async fn new(config: Configuration) -> Result<Self, String> {
-		let db = Database::new(
-			&config.db_url,
-			config.table_name.clone(),
-			config.send_message_table_name.clone(),
-			config.execute_table_name.clone(),
-		)
-		.await
-		.map_err(|e| std::format!("Failed to establish a connection with db. Reason: {}", e))?;
-
-		let node = avail_rust::Client::new(&config.avail_url)
-			.await
-			.map_err(|e| std::format!("Failed to establish a connection with avail node. Reason: {}", e.to_string()))?;
-		let next_height_to_index = define_next_height_to_index(config.block_height, &db, &node).await?;
-		let finalized_height = node.finalized().block_height().await.map_err(|e| e.to_string())?;
+	/// Creates DB and Node instance. Calculates start height. The built-in handlers and the configured tracked
+	/// calls are registered.
+	pub async fn new(config: Configuration, mode: Mode) -> Result<Self, String> {
+		let connections = connect(&config).await?;
+		let registry = Arc::new(CallRegistry::new(&config.tracked_calls)?);
+		Self::with_connections(config, mode, connections, registry).await
+	}
 
-		// Here we define what extrinsics we will follow
-		let tracked_calls: Vec<(u8, u8)> = vec![SendMessage::HEADER_INDEX, Execute::HEADER_INDEX];
-		let filter = Options::default().filter(tracked_calls);
+	/// Same as `new` but reuses existing connections and handlers. Used when several indexers run in one process.
+	pub(crate) async fn with_connections(
+		config: Configuration,
+		mode: Mode,
+		connections: Connections,
+		registry: Arc<CallRegistry>,
+	) -> Result<Self, String> {
+		let Connections { db, endpoints, finalized_heads } = connections;
+		let (_, node) = endpoints.client()?;
+		let (checkpoint_name, next_height_to_index, end_height) = match mode {
+			Mode::Live => {
+				let next_height_to_index = define_next_height_to_index(config.block_height, &db, &node).await?;
+				(checkpoint_table::INDEXER_CHECKPOINT.to_string(), next_height_to_index, None)
+			},
+			Mode::Backfill { from, to } => {
+				let checkpoint_name = checkpoint_table::backfill_checkpoint(from, to);
+				let next_height_to_index = match db.find_checkpoint(&checkpoint_name).await? {
+					Some(checkpoint) => checkpoint.block_height.saturating_add(1),
+					None => from,
+				};
+				(checkpoint_name, next_height_to_index, Some(to))
+			},
+		};
+		let finalized_height = *finalized_heads.borrow();
 
 		Ok(Self {
-			stats: IndexerStats::new(config.log_interval_ms),
-			node,
+			stats: IndexerStats::new(checkpoint_name.clone(), config.log_interval_ms),
 			db,
+			endpoints,
 			config,
+			checkpoint_name,
 			next_height_to_index,
+			end_height,
 			finalized_height,
-			filter,
+			finalized_heads,
+			registry,
+			batch: WriteBatch::default(),
+			last_flush: Instant::now(),
 		})
 	}
 
+	/// Routes the calls of `handler` to it from now on. Has to be called before `run`.
+	pub fn register_handler<H: ExtrinsicHandler>(&mut self, handler: H) {
+		Arc::make_mut(&mut self.registry).register(handler);
+	}
+
 	pub async fn run(mut self) -> Result<(), String> {
 		let max_task_count = self.config.max_task_count;
+		self.db.create_call_tables(self.registry.call_table_names()).await?;
 
 		info!(
+			checkpoint_name = self.checkpoint_name,
 			start_height = self.next_height_to_index,
+			end_height = ?self.end_height,
 			finalized_height = self.finalized_height,
 			max_task_count,
 			"Indexer up and running."
 		);
 
-		// Calculate how many tasks do we need.
-		let blocks_to_index_count = self.blocks_to_index_count();
-		let task_count = blocks_to_index_count.min(max_task_count);
-		if task_count < max_task_count {
-			info!(
-				blocks_to_index_count,
-				task_count = task_count,
-				"Not that many blocks to index. Reduced max task count"
-			);
-		} else {
-			info!(
-				blocks_to_index_count,
-				task_count = max_task_count,
-				"We have many blocks to index. Using max task count"
-			);
-		}
-
-		// Handles
-		let mut handles = Vec::with_capacity(task_count as usize);
-
-		info!(count = task_count, "Creating HTTP Node connections...");
-		// Create Task Params
-		let mut task_params =
-			create_task_params(&self.config.avail_url, task_count as usize, self.filter.clone()).await?;
+		let window = max_task_count.saturating_mul(WINDOW_PER_TASK);
+		let concurrency = ConcurrencyController::new(max_task_count);
+		let mut pipeline = Pipeline::new(
+			self.next_height_to_index,
+			window,
+			self.registry.clone(),
+			concurrency,
+			self.endpoints.clone(),
+		);
 
 		info!("Main loop started");
 		self.stats.checkpoint = Instant::now();
 		loop {
-			self.sleep_if_ahead().await;
-
-			if let Err(err) = self.update_task_count(&mut task_params).await {
-				terror!(error = err, "Failed to update task count. Sleeping and then retrying.");
+			// Once the whole range is processed whatever is left in the batch is written right away.
+			if let Err(err) = self.flush_batch(self.is_done()).await {
+				terror!(
+					error = err,
+					sleep_duration_secs = SLEEP_DURATION_ON_ERROR.as_secs(),
+					"Failed to write blocks to db. Sleeping and then retrying."
+				);
 				tokio::time::sleep(SLEEP_DURATION_ON_ERROR).await;
 				continue;
 			}
 
-			let processed_height = self.process_n_blocks(&mut task_params, &mut handles).await;
+			if self.is_done() {
+				info!(
+					last_indexed_block = self.next_height_to_index.saturating_sub(1),
+					block_indexed_count = self.stats.total_indexed,
+					"Range fully committed. Indexer stopping"
+				);
+				return Ok(());
+			}
+
+			self.sleep_if_ahead().await;
 
-			if let Some(processed_height) = processed_height.height {
-				self.stats.total_indexed += processed_height
-					.saturating_add(1)
-					.saturating_sub(self.next_height_to_index);
-				self.next_height_to_index = processed_height + 1;
+			self.update_task_count(&mut pipeline);
+			let scheduled = pipeline.schedule(self.target_height());
+			let waited = pipeline.wait(PIPELINE_WAIT_TIMEOUT).await;
+			for failure in scheduled.into_iter().chain(waited) {
+				log_failure(&failure);
+			}
+
+			// Written to the DB, together with the checkpoint, once the batch is flushed.
+			while let Some(result) = pipeline.pop_ready() {
+				self.batch.push(result.db_data, result.block_height, result.block_hash);
+				self.next_height_to_index = result.block_height + 1;
+				self.stats.total_indexed += 1;
 			}
 
 			self.stats.maybe_display_stats(
 				self.next_height_to_index.saturating_sub(1),
 				self.finalized_height,
 				self.blocks_to_index_count(),
+				pipeline.worker_count() as u32,
+				pipeline.concurrency(),
 			);
-
-			if let Some(err) = processed_height.error {
-				terror!(
-					error = err,
-					sleep_duration_secs = SLEEP_DURATION_ON_ERROR.as_secs(),
-					"Failed to sync some of of the blocks. Sleeping and then retrying."
-				);
-				tokio::time::sleep(SLEEP_DURATION_ON_ERROR).await;
-			}
 		}
 	}
 
-	async fn update_task_count(&self, task_params: &mut Vec<TaskParams>) -> Result<(), String> {
-		let expected_count = self.blocks_to_index_count().min(self.config.max_task_count) as usize;
-		let current_count = task_params.len();
-
+	fn update_task_count(&self, pipeline: &mut Pipeline) {
+		// The controller never allows more than `max_task_count` tasks.
+		let limit = pipeline.concurrency().limit();
+		let expected_count = self.blocks_to_index_count().min(limit).max(1) as usize;
+		let current_count = pipeline.worker_count();
 		if expected_count == current_count {
-			return Ok(());
+			return;
 		}
 
+		pipeline.set_worker_count(expected_count);
 		if expected_count > current_count {
-			let diff = expected_count.saturating_sub(current_count);
-			for _ in 0..diff {
-				let node = Client::new(&self.config.avail_url).await.map_err(|e| e.to_string())?;
-				task_params.push(TaskParams::new(node, self.filter.clone()));
-			}
+			info!(previous_task_count = current_count, new_task_count = expected_count, "Task count has increased");
+		} else {
+			info!(previous_task_count = current_count, new_task_count = expected_count, "Task count has decreased");
+		}
+	}
 
-			let new_task_count = task_params.len();
-			info!(previous_task_count = current_count, new_task_count, "Task count has increased");
+	/// Writes the collected blocks once the batch is full or the flush interval has passed.
+	/// With `force` set the batch is written regardless. On failure the batch is kept so it can be retried.
+	async fn flush_batch(&mut self, force: bool) -> Result<(), String> {
+		let Some((block_height, block_hash)) = self.batch.last_block else {
+			self.last_flush = Instant::now();
+			return Ok(());
+		};
+
+		let is_full = self.batch.block_count >= self.config.write_batch_size;
+		let is_due = self.last_flush.elapsed() >= Duration::from_millis(self.config.write_flush_interval_ms as u64);
+		if !(force || is_full || is_due) {
 			return Ok(());
 		}
 
-		task_params.truncate(expected_count.max(1));
-		let new_task_count = task_params.len();
-		info!(previous_task_count = current_count, new_task_count, "Task count has decreased");
+		let checkpoint = checkpoint_table::TableEntry::new(&self.checkpoint_name, block_height, block_hash);
+		self.db.insert(&self.batch.data, checkpoint).await?;
 
+		self.batch = WriteBatch::default();
+		self.last_flush = Instant::now();
 		Ok(())
 	}
 
-	async fn process_n_blocks(
-		&self,
-		task_params: &mut Vec<TaskParams>,
-		handles: &mut Vec<JoinHandle<Result<TaskResult, String>>>,
-	) -> ProcessedHeight {
-		// Update bock height of every task param
-		update_task_params(self.next_height_to_index, task_params);
-		spawn_tasks(handles, &task_params);
-		process_results(&self.db, handles).await
-	}
-
+	/// Picks up the latest finalized height. Once every finalized block is indexed, the batch is written and
+	/// the indexer waits for the next finalized head.
 	async fn sleep_if_ahead(&mut self) {
 		loop {
+			self.finalized_height = self.finalized_height.max(*self.finalized_heads.borrow_and_update());
 			if self.finalized_height >= self.next_height_to_index {
 				return;
 			}
 
-			self.finalized_height = self
-				.node
-				.finalized()
-				.block_height()
-				.await
-				.unwrap_or_else(|_| self.finalized_height);
+			// We are caught up so there is no point in waiting for the batch to fill up.
+			if let Err(err) = self.flush_batch(true).await {
+				terror!(error = err, "Failed to write blocks to db. Retrying after sleep.");
+			}
 
-			if self.next_height_to_index > self.finalized_height {
-				// Nothing to do besides sleeping.
-				tokio::time::sleep(Duration::from_secs(60)).await;
-				continue;
+			// The follower only stops when it panics. Sleep instead of spinning in that case.
+			if self.finalized_heads.changed().await.is_err() {
+				tokio::time::sleep(SLEEP_DURATION_ON_ERROR).await;
 			}
 		}
 	}
 
 	fn blocks_to_index_count(&self) -> u32 {
-		self.finalized_height
+		self.target_height()
 			.saturating_add(1)
 			.saturating_sub(self.next_height_to_index)
 	}
+
+	/// Highest height that can be indexed right now
+	fn target_height(&self) -> u32 {
+		match self.end_height {
+			Some(end_height) => self.finalized_height.min(end_height),
+			None => self.finalized_height,
+		}
+	}
+
+	fn is_done(&self) -> bool {
+		self.end_height.is_some_and(|x| self.next_height_to_index > x)
+	}
+}
+
+fn log_failure(failure: &Failure) {
+	match failure.retry_in {
+		Some(retry_in) => warn!(
+			error = %failure.error,
+			block_height = failure.block_height,
+			attempt = failure.attempt,
+			permanent = failure.error.is_permanent(),
+			retry_in_ms = retry_in.as_millis() as u64,
+			"Failed to sync block. Other blocks keep going and this one is retried after a backoff."
+		),
+		None => terror!(
+			error = %failure.error,
+			block_height = failure.block_height,
+			attempt = failure.attempt,
+			"Block keeps failing. It is quarantined and skipped."
+		),
+	}
+}
+
+/// Indexes the finalized head right away. If the start height is more than `live_tail_threshold` blocks behind
+/// it, the blocks in between are handed to a backfill indexer that runs next to the live one. Unfinished
+/// backfills from previous runs are resumed as well. Every indexer uses the handlers of `registry`.
+///
+/// The indexers split `max_task_count` between them, so together they never run more fetches than configured.
+/// Returns the first error of any of the indexers. Unfinished backfills are resumed on next start.
+pub async fn run_live(mut config: Configuration, registry: CallRegistry) -> Result<(), String> {
+	let connections = connect(&config).await?;
+	let registry = Arc::new(registry);
+
+	// Otherwise every restart with the same start height would hand the same blocks to another backfill.
+	if let Some(block_height) = config.block_height {
+		let checkpoint = connections
+			.db
+			.find_checkpoint(checkpoint_table::INDEXER_CHECKPOINT)
+			.await?;
+		if let Some(checkpoint) = checkpoint.filter(|x| x.block_height >= block_height) {
+			info!(
+				block_height,
+				checkpoint_height = checkpoint.block_height,
+				"Live checkpoint is already past the configured block height. Resuming from the checkpoint"
+			);
+			config.block_height = None;
+		}
+	}
+
+	if split_off_backfill(&config, &connections).await? {
+		// The live checkpoint now points at the block before the finalized head.
+		config.block_height = None;
+	}
+
+	let backfills = connections.db.find_unfinished_backfills().await?;
+	let indexer_count = backfills.len() as u32 + 1;
+	config.max_task_count = (config.max_task_count / indexer_count).max(1);
+
+	let mut indexers = JoinSet::new();
+	for (from, to) in backfills {
+		let mode = Mode::Backfill { from, to };
+		let indexer = Indexer::with_connections(config.clone(), mode, connections.clone(), registry.clone()).await?;
+		indexers.spawn(async move {
+			indexer
+				.run()
+				.await
+				.map_err(|e| std::format!("Backfill {}..={} failed. Error: {}", from, to, e))
+		});
+	}
+
+	let indexer = Indexer::with_connections(config, Mode::Live, connections, registry).await?;
+	indexers.spawn(indexer.run());
+
+	while let Some(result) = indexers.join_next().await {
+		result.map_err(|e| std::format!("Indexer task failed. Error: {}", e.to_string()))??;
+	}
+
+	Ok(())
+}
+
+/// Registers the blocks between the start height and the finalized head as a backfill range and moves the live
+/// checkpoint right before the finalized head. Both checkpoints are written in one transaction, so every height
+/// belongs to exactly one of the two indexers.
+async fn split_off_backfill(config: &Configuration, connections: &Connections) -> Result<bool, String> {
+	let Connections { db, endpoints, finalized_heads } = connections;
+	let (_, node) = endpoints.client()?;
+	if config.live_tail_threshold == 0 {
+		return Ok(false);
+	}
+
+	let start_height = define_next_height_to_index(config.block_height, db, &node).await?;
+	let finalized_height = *finalized_heads.borrow();
+	// The backfill checkpoint points at the block before the range, which doesn't exist for genesis.
+	if start_height == 0 || finalized_height.saturating_sub(start_height) <= config.live_tail_threshold {
+		return Ok(false);
+	}
+
+	let (from, to) = (start_height, finalized_height - 1);
+	let backfill = checkpoint_table::TableEntry::new(
+		&checkpoint_table::backfill_checkpoint(from, to),
+		from - 1,
+		fetch_block_hash(&node, from - 1).await?,
+	);
+	let live =
+		checkpoint_table::TableEntry::new(checkpoint_table::INDEXER_CHECKPOINT, to, fetch_block_hash(&node, to).await?);
+	db.insert_checkpoints(vec![backfill, live]).await?;
+
+	info!(
+		from,
+		to, "Start height is far behind the finalized head. Blocks in between are handed to a backfill"
+	);
+	Ok(true)
+}
+
+async fn connect(config: &Configuration) -> Result<Connections, String> {
+	let db = Database::from_config(config)
+		.await
+		.map_err(|e| std::format!("Failed to establish a connection with db. Reason: {}", e))?;
+
+	let endpoints = EndpointPool::connect(&config.avail_endpoints, config.rpc_connection_count).await?;
+	let finalized_heads = finalized::follow(endpoints.clone()).await?;
+
+	Ok(Connections { db, endpoints, finalized_heads })
 }
 
 #[derive(Clone)]
-pub struct TaskParams {
+pub(crate) struct TaskParams {
 	pub node: Client,
-	pub filter: Options,
+	/// Index of the endpoint `node` is connected to
+	pub endpoint: usize,
+	pub registry: Arc<CallRegistry>,
 	pub block_height: u32,
 }
 
 impl TaskParams {
-	pub fn new(node: Client, filter: Options) -> Self {
-		Self { node, filter, block_height: 0 }
+	pub fn new(node: Client, endpoint: usize, registry: Arc<CallRegistry>) -> Self {
+		Self { node, endpoint, registry, block_height: 0 }
 	}
 }
 
-struct TaskResult {
+pub(crate) struct TaskResult {
 	pub db_data: DataForDatabase,
 	pub block_height: u32,
+	pub block_hash: H256,
+	/// How many RPC requests it took to fetch the block
+	pub request_count: u32,
 }
 
-#[derive(Debug)]
-struct ProcessedHeight {
-	pub height: Option<u32>,
-	pub error: Option<String>,
+/// Why a block could not be indexed.
+#[derive(Debug, Clone)]
+pub(crate) enum TaskError {
+	/// The node could not be reached, timed out or returned an error. Retrying is expected to succeed.
+	Transient(String),
+	/// The block was fetched but could not be decoded. Retrying is expected to give the same result.
+	/// `dead_letter` holds the extrinsic that could not be decoded, if there is one.
+	Permanent {
+		block_hash: H256,
+		error: String,
+		dead_letter: Option<Box<dead_letter_table::TableEntry>>,
+	},
+	/// The block was not fetched in time. Retrying is expected to succeed.
+	Timeout(Duration),
 }
 
-impl ProcessedHeight {
-	pub fn new(height: Option<u32>, error: Option<String>) -> Self {
-		Self { height, error }
+impl TaskError {
+	pub fn is_permanent(&self) -> bool {
+		matches!(self, TaskError::Permanent { .. })
 	}
 }
 
-fn update_task_params(start_height: u32, task_params: &mut Vec<TaskParams>) {
-	for (i, param) in task_params.iter_mut().enumerate() {
-		param.block_height = start_height + i as u32;
+impl std::fmt::Display for TaskError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			TaskError::Transient(error) => write!(f, "{}", error),
+			TaskError::Permanent { error, .. } => write!(f, "{}", error),
+			TaskError::Timeout(timeout) => write!(f, "Block fetch timed out after {}s", timeout.as_secs()),
+		}
 	}
 }
 
-async fn create_task_params(avail_url: &str, task_count: usize, filter: Options) -> Result<Vec<TaskParams>, String> {
-	let mut task_params: Vec<TaskParams> = Vec::with_capacity(task_count);
-	for _ in 0..task_count {
-		let node = Client::new(avail_url)
-			.await
-			.map_err(|e| std::format!("Failed to establish a connection with avail node. Reason: {}", e.to_string()))?;
-		task_params.push(TaskParams::new(node, filter.clone()));
+pub(crate) fn update_task_params(start_height: u32, task_params: &mut Vec<TaskParams>) {
+	for (i, param) in task_params.iter_mut().enumerate() {
+		param.block_height = start_height + i as u32;
 	}
+}
 
-	Ok(task_params)
+/// Every task borrows a client from the pool.
+pub(crate) fn create_task_params(
+	endpoints: &EndpointPool,
+	task_count: usize,
+	registry: Arc<CallRegistry>,
+) -> Result<Vec<TaskParams>, String> {
+	(0..task_count)
+		.map(|_| {
+			let (endpoint, node) = endpoints.client()?;
+			Ok(TaskParams::new(node, endpoint, registry.clone()))
+		})
+		.collect()
 }
 
-fn spawn_tasks(handles: &mut Vec<JoinHandle<Result<TaskResult, String>>>, params: &[TaskParams]) {
+pub(crate) fn spawn_tasks(handles: &mut Vec<JoinHandle<Result<TaskResult, TaskError>>>, params: &[TaskParams]) {
 	handles.clear();
 	for param in params.iter() {
 		let params = param.clone();
@@ -253,55 +476,69 @@ fn spawn_tasks(handles: &mut Vec<JoinHandle<Result<TaskResult, String>>>, params
 	}
 }
 
-async fn process_results(db: &Database, handles: &mut [JoinHandle<Result<TaskResult, String>>]) -> ProcessedHeight {
-	let mut processed_height = None;
-	for handle in handles {
-		let result = match handle.await {
-			Ok(x) => x,
-			Err(err) => {
-				return ProcessedHeight::new(processed_height, Some(err.to_string()));
-			},
-		};
-		let result = match result {
-			Ok(x) => x,
-			Err(err) => {
-				return ProcessedHeight::new(processed_height, Some(err));
-			},
-		};
-
-		if let Err(error) = db.insert(result.db_data).await {
-			return ProcessedHeight::new(processed_height, Some(error));
-		}
-		processed_height = Some(result.block_height);
-	}
+pub(crate) async fn task(params: TaskParams) -> Result<TaskResult, TaskError> {
+	let TaskParams { node, registry, block_height, .. } = params;
+	// The block hash is always needed as it is recorded in the checkpoint.
+	let block_hash = fetch_block_hash(&node, block_height)
+		.await
+		.map_err(TaskError::Transient)?;
 
-	ProcessedHeight::new(processed_height, None)
+	let header = node
+		.chain()
+		.block_header(Some(block_hash))
+		.await
+		.map_err(|e| TaskError::Transient(e.to_string()))?
+		.ok_or_else(|| {
+			TaskError::Transient(std::format!("Failed to fetch block header for block height: {}", block_height))
+		})?;
+
+	// The inherents come with the tracked calls, so every block takes a single extrinsics request.
+	let mut filter = registry.keys();
+	filter.extend(INHERENTS);
+	let block = avail_rust::block::encoded::BlockEncodedExtrinsicsQuery::new(node.clone(), block_hash.into());
+	let list = block
+		.all(Options::default().filter(filter))
+		.await
+		.map_err(|e| TaskError::Transient(e.to_string()))?;
+
+	// Every block is recorded in the block table so the timestamp is needed even without tracked extrinsics.
+	// Genesis has none and is stored with the Unix epoch.
+	let (timestamp, failed_txs) = read_block_inherents(&list, block_height, block_hash)?;
+	let timestamp = timestamp.unwrap_or(0);
+
+	let tracked: Vec<BlockEncodedExtrinsic> = list
+		.into_iter()
+		.filter(|x| registry.get((x.metadata.pallet_id, x.metadata.variant_id)).is_some())
+		.collect();
+	// Block hash, header, extrinsics, and the events of every tracked extrinsic
+	let request_count = 3 + tracked.len() as u32;
+
+	let mut db_data = if tracked.is_empty() {
+		DataForDatabase::default()
+	} else {
+		convert_extrinsics_to_table_entries(&node, &registry, tracked, block_height, block_hash, timestamp, failed_txs)
+			.await?
+	};
+	db_data.block_entries.push(block_table::TableEntry {
+		block_height,
+		block_hash,
+		parent_hash: header.parent_hash,
+		block_timestamp: timestamp,
+		tracked_ext_count: db_data.main_entries.len() as u32,
+	});
+
+	Ok(TaskResult { db_data, block_height, block_hash, request_count })
 }
 
-async fn task(params: TaskParams) -> Result<TaskResult, String> {
-	let TaskParams { node, filter, block_height } = params;
-	let block = avail_rust::block::encoded::BlockEncodedExtrinsicsQuery::new(node.clone(), block_height.into());
-	let list = block.all(filter).await.map_err(|e| e.to_string())?;
-
-	if list.is_empty() {
-		return Ok(TaskResult { db_data: Default::default(), block_height });
-	}
-
-	let block_hash = node
-		.chain()
+async fn fetch_block_hash(node: &Client, block_height: u32) -> Result<H256, String> {
+	node.chain()
 		.block_hash(Some(block_height))
 		.await
 		.map_err(|e| e.to_string())?
-		.ok_or(std::format!("Failed to fetch block hash for block height: {}", block_height))?;
-
-	let (timestamp, failed_txs) = fetch_block_timestamp_and_failed_txs(node.clone(), block_hash).await?;
-	let db_data =
-		convert_extrinsics_to_table_entries(&node, list, block_height, block_hash, timestamp, failed_txs).await?;
-
-	Ok(TaskResult { db_data, block_height })
+		.ok_or(std::format!("Failed to fetch block hash for block height: {}", block_height))
 }
 
-pub async fn define_next_height_to_index(
+pub(crate) async fn define_next_height_to_index(
 	block_height: Option<u32>,
 	db: &Database,
 	node: &avail_rust::Client,
@@ -310,6 +547,11 @@ pub async fn define_next_height_to_index(
 		return Ok(block_height);
 	}
 
+	if let Some(checkpoint) = db.find_checkpoint(checkpoint_table::INDEXER_CHECKPOINT).await? {
+		return Ok(checkpoint.block_height.saturating_add(1));
+	}
+
+	// Deployments without a checkpoint fall back to the last block that had a tracked extrinsic.
 	if let Some(block_height) = db.find_highest_block_height().await? {
 		return Ok(block_height);
 	}
@@ -0,0 +1,58 @@
+//! Indexes the Vector bridge extrinsics of Avail into Postgres.
+//!
+//! The `new-bridge-indexer` binary runs the indexer as configured. Crates that need to index calls of their own
+//! implement `handler::ExtrinsicHandler` and register it with `indexer::Indexer::register_handler`, or with
+//! `registry::CallRegistry::register` before calling `indexer::run_live` or `verify`.
+
+mod backoff;
+mod common;
+mod concurrency;
+pub mod configuration;
+mod db;
+mod endpoints;
+mod finalized;
+pub mod handler;
+pub mod indexer;
+mod pipeline;
+pub mod registry;
+mod stats;
+mod verify;
+
+pub use db::repair::WrappedIds;
+/// Encoders and typed readers of the column types, for crates that query the tables
+pub use db::types;
+pub use verify::VerifyReport;
+
+use configuration::Configuration;
+use db::Database;
+use registry::CallRegistry;
+
+/// Connects to the DB and applies all pending migrations.
+pub async fn migrate(config: &Configuration) -> Result<(), String> {
+	Database::from_config(config)
+		.await
+		.map_err(|e| std::format!("Failed to apply migrations. Reason: {}", e))?;
+	Ok(())
+}
+
+/// Applies pending migrations and rewrites the message ids that were stored wrapped to negative numbers.
+pub async fn repair_ids(config: &Configuration) -> Result<WrappedIds, String> {
+	let db = Database::from_config(config)
+		.await
+		.map_err(|e| std::format!("Failed to apply migrations. Reason: {}", e))?;
+	db::repair::repair(&db).await
+}
+
+/// Compares the stored blocks `from..=to` with the chain and, with `repair` set, rewrites the ones that differ.
+///
+/// Only the handlers of `registry` are known, so it has to hold the same handlers the indexer runs with.
+/// Otherwise the rows of the missing ones are reported as extra and deleted by `repair`.
+pub async fn verify(
+	config: &Configuration,
+	registry: CallRegistry,
+	from: u32,
+	to: u32,
+	repair: bool,
+) -> Result<VerifyReport, String> {
+	verify::run(config, registry, from, to, repair).await
+}
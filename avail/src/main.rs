@@ -1,18 +1,23 @@
-mod common;
-mod configuration;
-mod db;
-mod indexer;
-mod stats;
+mod cli;
 
-use crate::{configuration::Observability, indexer::Indexer};
+use crate::cli::Command;
 use internal_utils::{TracingBuilder, TracingGuards, TracingOtelParams};
+use new_bridge_indexer::{
+	configuration::{self, Observability},
+	indexer::{self, Indexer, Mode},
+	registry::CallRegistry,
+};
+use std::process::ExitCode;
 use tokio::runtime::Runtime;
-use tracing::{error as terror, info};
+use tracing::{error as terror, info, warn};
 
 const SERVICE_NAME: &'static str = env!("CARGO_CRATE_NAME");
 const SERVICE_VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
-fn main() {
+fn main() -> ExitCode {
+	// There is no point in retrying. We will get the same error back each time.
+	let command = Command::from_args().expect("Command line arguments should not be malformed");
+
 	// Load configuration
 	// There is no point in retrying. We will get the same error back each time.
 	let config = configuration::Configuration::new().expect("Configuration file should not be malformed");
@@ -22,17 +27,29 @@ fn main() {
 	let service_name = obs.service_name.clone().unwrap_or_else(|| SERVICE_NAME.into());
 	let service_version = obs.service_version.clone().unwrap_or_else(|| SERVICE_VERSION.into());
 	info!(
+		command = ?command,
 		traces_endpoint = ?obs.traces_endpoint,
 		metrics_endpoint = ?obs.metrics_endpoint,
 		logs_endpoint = ?obs.logs_endpoint,
 		service_name = service_name,
 		service_version = service_version,
-		avail_url = config.avail_url,
+		avail_endpoints = ?config.avail_endpoints,
 		main_table_name = config.table_name,
 		send_message_table_name = config.send_message_table_name,
 		execute_table_name = config.execute_table_name,
+		checkpoint_table_name = config.checkpoint_table_name,
+		event_table_name = config.event_table_name,
+		bridge_message_table_name = config.bridge_message_table_name,
+		block_table_name = config.block_table_name,
+		quarantine_table_name = config.quarantine_table_name,
+		dead_letter_table_name = config.dead_letter_table_name,
 		block_height = ?config.block_height,
 		max_task_count = config.max_task_count,
+		rpc_connection_count = config.rpc_connection_count,
+		write_batch_size = config.write_batch_size,
+		write_flush_interval_ms = config.write_flush_interval_ms,
+		live_tail_threshold = config.live_tail_threshold,
+		tracked_calls = ?config.tracked_calls,
 		log_interval_ms = config.log_interval_ms,
 	);
 
@@ -42,21 +59,80 @@ fn main() {
 		Ok(r) => r,
 		Err(err) => {
 			terror!("Failed to create runtime. Existing program. Reason: {}", err);
-			return;
+			return ExitCode::FAILURE;
 		},
 	};
 
+	// Bounded commands rely on the exit code to tell whether they finished successfully.
 	runtime.block_on(async move {
-		let t1 = tokio::spawn(async {
-			let indexer = Indexer::new(config).await?;
-			indexer.run().await
+		let t1 = tokio::spawn(async move {
+			match command {
+				Command::Run => {
+					let registry = CallRegistry::new(&config.tracked_calls)?;
+					indexer::run_live(config, registry).await
+				},
+				Command::Backfill { from, to } => {
+					let indexer = Indexer::new(config, Mode::Backfill { from, to }).await?;
+					indexer.run().await
+				},
+				Command::Migrate => {
+					new_bridge_indexer::migrate(&config).await?;
+					info!("Migrations applied. Exiting");
+					Ok(())
+				},
+				Command::RepairIds => {
+					let found = new_bridge_indexer::repair_ids(&config).await?;
+					if found.main_ids > 0 {
+						warn!(
+							count = found.main_ids,
+							"Main table ids don't match block_height and ext_index. They need to be reindexed"
+						);
+					}
+					info!(
+						execute_message_ids = found.execute_message_ids,
+						bridge_message_ids = found.bridge_message_ids,
+						nothing_to_repair = found.is_empty(),
+						"Wrapped ids repaired. Exiting"
+					);
+					Ok(())
+				},
+				Command::Verify { from, to, repair } => {
+					// Same handlers as `Command::Run`. Crates that register handlers in code call `verify` with
+					// their own registry.
+					let registry = CallRegistry::new(&config.tracked_calls)?;
+					let report = new_bridge_indexer::verify(&config, registry, from, to, repair).await?;
+					info!(
+						checked_blocks = report.checked_blocks,
+						missing_blocks = report.missing_blocks,
+						mismatched_blocks = report.mismatched_blocks,
+						missing_rows = report.missing_rows,
+						extra_rows = report.extra_rows,
+						differing_blocks = report.differing_blocks,
+						repaired_blocks = report.repaired_blocks,
+						failed_blocks = report.failed_blocks,
+						consistent = report.is_consistent(),
+						"Verification finished. Exiting"
+					);
+					if !report.is_resolved() {
+						return Err(String::from("Blocks differ from the chain or could not be checked"));
+					}
+					Ok(())
+				},
+			}
 		});
 
 		match t1.await {
-			Err(err) => terror!(error = err.to_string(), "Indexer returned an error. Indexer shutting down"),
-			_ => (),
+			Err(err) => {
+				terror!(error = err.to_string(), "Indexer returned an error. Indexer shutting down");
+				ExitCode::FAILURE
+			},
+			Ok(Err(err)) => {
+				terror!(error = err, "Indexer returned an error. Indexer shutting down");
+				ExitCode::FAILURE
+			},
+			Ok(Ok(())) => ExitCode::SUCCESS,
 		}
-	});
+	})
 }
 
 pub fn setup_observability(config: &Observability) -> Result<TracingGuards, Box<dyn std::error::Error + Send + Sync>> {
@@ -0,0 +1,396 @@
+//! Sliding-window scheduler for block fetching.
+//!
+//! Every worker picks up the next height as soon as it is idle, so one slow block doesn't hold up the others.
+//! Finished blocks wait in a reorder buffer and are handed out strictly in height order. The window limits how
+//! far fetching may run ahead of the lowest block that is not finished yet, which bounds the buffer size.
+//!
+//! A failed height is retried on its own with a jittered exponential backoff while the other heights keep
+//! going. A height that keeps failing permanently, or keeps failing at all while later heights are fetched fine,
+//! is quarantined: it is handed out with nothing but a quarantine record, so the chain walk moves on.
+//!
+//! Every finished fetch is reported to the concurrency controller, which decides how many workers should run, and
+//! to the endpoint pool, which tracks the health of the node the worker is connected to.
+
+use crate::{
+	backoff::Backoff,
+	concurrency::{ConcurrencyController, Outcome},
+	db::{DataForDatabase, quarantine_table},
+	endpoints::EndpointPool,
+	indexer::{TaskError, TaskParams, TaskResult, task},
+	registry::CallRegistry,
+};
+use avail_rust::H256;
+use std::{
+	collections::{BTreeMap, HashMap},
+	sync::Arc,
+	time::{Duration, Instant},
+};
+use tokio::task::{Id, JoinSet};
+
+/// Delay between attempts of a failed height
+pub const RETRY_BACKOFF: Backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60));
+/// A permanent failure is retried this many times in total before the block is quarantined. A few retries
+/// guard against a node that returned an incomplete block.
+pub const PERMANENT_FAILURE_ATTEMPTS: u32 = 3;
+/// Any failure is retried this many times in total before the block is quarantined, as long as a later block was
+/// fetched in the meantime. Node errors and undecodable blocks look the same, but only the latter keep failing
+/// while the node serves the blocks around them.
+pub const TRANSIENT_FAILURE_ATTEMPTS: u32 = 10;
+/// A fetch that takes longer than this is abandoned and retried
+pub const TASK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A failed fetch reported by `Pipeline::wait`
+pub struct Failure {
+	pub block_height: u32,
+	/// How many times in a row this height has failed
+	pub attempt: u32,
+	pub error: TaskError,
+	/// None if the block was quarantined
+	pub retry_in: Option<Duration>,
+}
+
+pub struct Pipeline {
+	/// Next height that has never been handed to a worker
+	next_height_to_fetch: u32,
+	/// Lowest height that has not been handed out by `pop_ready` yet
+	next_height_to_commit: u32,
+	window: u32,
+	worker_count: usize,
+	registry: Arc<CallRegistry>,
+	in_flight: JoinSet<Result<TaskResult, TaskError>>,
+	/// Height, endpoint and start of every in flight fetch
+	in_flight_heights: HashMap<Id, (u32, usize, Instant)>,
+	reorder_buffer: BTreeMap<u32, TaskResult>,
+	/// Heights whose fetch failed together with the instant at which they can be fetched again
+	retries: BTreeMap<u32, Instant>,
+	/// Consecutive failures of every height that has failed and not succeeded since
+	attempts: HashMap<u32, u32>,
+	concurrency: ConcurrencyController,
+	endpoints: Arc<EndpointPool>,
+}
+
+impl Pipeline {
+	pub fn new(
+		start_height: u32,
+		window: u32,
+		registry: Arc<CallRegistry>,
+		concurrency: ConcurrencyController,
+		endpoints: Arc<EndpointPool>,
+	) -> Self {
+		Self {
+			next_height_to_fetch: start_height,
+			next_height_to_commit: start_height,
+			window: window.max(1),
+			worker_count: 1,
+			registry,
+			in_flight: JoinSet::new(),
+			in_flight_heights: HashMap::new(),
+			reorder_buffer: BTreeMap::new(),
+			retries: BTreeMap::new(),
+			attempts: HashMap::new(),
+			concurrency,
+			endpoints,
+		}
+	}
+
+	pub fn concurrency(&self) -> &ConcurrencyController {
+		&self.concurrency
+	}
+
+	pub fn worker_count(&self) -> usize {
+		self.worker_count
+	}
+
+	/// Sets how many fetches may run at the same time. Busy workers are never interrupted, so lowering the count
+	/// takes effect as they finish.
+	pub fn set_worker_count(&mut self, count: usize) {
+		self.worker_count = count;
+	}
+
+	/// Hands heights up to `target_height` (inclusive) to idle workers. Failed heights that can be retried go
+	/// first, then new heights as long as they are inside the window. Every fetch borrows a client from the
+	/// endpoint pool. If the pool has no client the height fails like any fetch and the failure is returned.
+	pub fn schedule(&mut self, target_height: u32) -> Option<Failure> {
+		while self.in_flight.len() < self.worker_count {
+			let block_height = self.next_height(target_height)?;
+			let (endpoint, node) = match self.endpoints.client() {
+				Ok(x) => x,
+				Err(err) => return Some(self.on_failure(block_height, TaskError::Transient(err))),
+			};
+			let mut params = TaskParams::new(node, endpoint, self.registry.clone());
+			params.block_height = block_height;
+			let handle = self.in_flight.spawn(async move {
+				match tokio::time::timeout(TASK_TIMEOUT, task(params)).await {
+					Ok(result) => result,
+					Err(_) => Err(TaskError::Timeout(TASK_TIMEOUT)),
+				}
+			});
+			self.in_flight_heights
+				.insert(handle.id(), (block_height, endpoint, Instant::now()));
+		}
+
+		None
+	}
+
+	/// Waits until a worker finishes or `timeout` passes. Returns the failure if the fetch failed.
+	pub async fn wait(&mut self, timeout: Duration) -> Option<Failure> {
+		if self.in_flight.is_empty() {
+			tokio::time::sleep(timeout).await;
+			return None;
+		}
+
+		let joined = tokio::time::timeout(timeout, self.in_flight.join_next_with_id())
+			.await
+			.ok()??;
+		match joined {
+			Ok((id, result)) => {
+				let (block_height, endpoint, started_at) = self.in_flight_heights.remove(&id)?;
+				let outcome = match &result {
+					Ok(result) => Some(Outcome::Success { request_count: result.request_count }),
+					Err(TaskError::Transient(_)) => Some(Outcome::Error),
+					Err(TaskError::Timeout(_)) => Some(Outcome::Timeout),
+					// Says nothing about the node
+					Err(TaskError::Permanent { .. }) => None,
+				};
+				if let Some(outcome) = outcome {
+					self.concurrency.record(outcome, started_at.elapsed());
+					self.endpoints
+						.report(endpoint, matches!(outcome, Outcome::Success { .. }));
+				}
+
+				match result {
+					Ok(result) => {
+						self.attempts.remove(&block_height);
+						self.reorder_buffer.insert(block_height, result);
+						None
+					},
+					Err(err) => Some(self.on_failure(block_height, err)),
+				}
+			},
+			Err(err) => {
+				let (block_height, _, _) = self.in_flight_heights.remove(&err.id())?;
+				Some(self.on_failure(block_height, TaskError::Transient(err.to_string())))
+			},
+		}
+	}
+
+	/// Next finished block in height order
+	pub fn pop_ready(&mut self) -> Option<TaskResult> {
+		let result = self.reorder_buffer.remove(&self.next_height_to_commit)?;
+		self.next_height_to_commit += 1;
+		Some(result)
+	}
+
+	fn on_failure(&mut self, block_height: u32, error: TaskError) -> Failure {
+		let attempt = self.attempts.entry(block_height).or_default();
+		*attempt += 1;
+		let attempt = *attempt;
+
+		let is_quarantined = match &error {
+			TaskError::Permanent { .. } => attempt >= PERMANENT_FAILURE_ATTEMPTS,
+			TaskError::Transient(_) | TaskError::Timeout(_) => {
+				let later_fetched = self
+					.reorder_buffer
+					.range(block_height.saturating_add(1)..)
+					.next()
+					.is_some();
+				attempt >= TRANSIENT_FAILURE_ATTEMPTS && later_fetched
+			},
+		};
+		if is_quarantined {
+			self.quarantine(block_height, &error, attempt);
+			return Failure { block_height, attempt, error, retry_in: None };
+		}
+
+		let retry_in = RETRY_BACKOFF.delay(attempt);
+		self.retries.insert(block_height, Instant::now() + retry_in);
+		Failure { block_height, attempt, error, retry_in: Some(retry_in) }
+	}
+
+	/// Hands the height out with only a quarantine record. The block hash is zero if it was never fetched.
+	fn quarantine(&mut self, block_height: u32, error: &TaskError, attempt: u32) {
+		self.attempts.remove(&block_height);
+		let (block_hash, dead_letter) = match error {
+			TaskError::Permanent { block_hash, dead_letter, .. } => (*block_hash, dead_letter.as_deref().cloned()),
+			TaskError::Transient(_) | TaskError::Timeout(_) => (H256::zero(), None),
+		};
+
+		let mut db_data = DataForDatabase::default();
+		let entry = quarantine_table::TableEntry::new(block_height, block_hash, error.to_string(), attempt);
+		db_data.quarantine_entries.push(entry);
+		db_data.dead_letter_entries.extend(dead_letter);
+		let result = TaskResult { db_data, block_height, block_hash, request_count: 0 };
+		self.reorder_buffer.insert(block_height, result);
+	}
+
+	fn next_height(&mut self, target_height: u32) -> Option<u32> {
+		let now = Instant::now();
+		let retry = self.retries.iter().find(|(_, at)| **at <= now).map(|(h, _)| *h);
+		if let Some(block_height) = retry {
+			self.retries.remove(&block_height);
+			return Some(block_height);
+		}
+
+		let window_end = self.next_height_to_commit.saturating_add(self.window);
+		if self.next_height_to_fetch > target_height || self.next_height_to_fetch >= window_end {
+			return None;
+		}
+
+		let block_height = self.next_height_to_fetch;
+		self.next_height_to_fetch += 1;
+		Some(block_height)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn pipeline(start_height: u32, window: u32) -> Pipeline {
+		let registry = Arc::new(CallRegistry::new(&[]).unwrap());
+		let endpoints = Arc::new(EndpointPool::new(&[], 1));
+		Pipeline::new(start_height, window, registry, ConcurrencyController::new(4), endpoints)
+	}
+
+	fn result(block_height: u32) -> TaskResult {
+		TaskResult {
+			db_data: DataForDatabase::default(),
+			block_height,
+			block_hash: H256::default(),
+			request_count: 1,
+		}
+	}
+
+	fn permanent() -> TaskError {
+		TaskError::Permanent {
+			block_hash: H256::default(),
+			error: String::from("bad block"),
+			dead_letter: None,
+		}
+	}
+
+	#[test]
+	fn next_height_stays_inside_window() {
+		let mut pipeline = pipeline(10, 3);
+		assert_eq!(pipeline.next_height(100), Some(10));
+		assert_eq!(pipeline.next_height(100), Some(11));
+		assert_eq!(pipeline.next_height(100), Some(12));
+		assert_eq!(pipeline.next_height(100), None);
+
+		// Committing the lowest height moves the window forward by one.
+		pipeline.reorder_buffer.insert(10, result(10));
+		assert!(pipeline.pop_ready().is_some());
+		assert_eq!(pipeline.next_height(100), Some(13));
+		assert_eq!(pipeline.next_height(100), None);
+	}
+
+	#[test]
+	fn next_height_stops_at_target() {
+		let mut pipeline = pipeline(10, 8);
+		assert_eq!(pipeline.next_height(11), Some(10));
+		assert_eq!(pipeline.next_height(11), Some(11));
+		assert_eq!(pipeline.next_height(11), None);
+	}
+
+	#[test]
+	fn next_height_prefers_due_retries() {
+		let mut pipeline = pipeline(10, 8);
+		assert_eq!(pipeline.next_height(100), Some(10));
+		assert_eq!(pipeline.next_height(100), Some(11));
+
+		pipeline.retries.insert(10, Instant::now());
+		pipeline.retries.insert(11, Instant::now() + Duration::from_secs(60));
+		assert_eq!(pipeline.next_height(100), Some(10));
+		assert_eq!(pipeline.next_height(100), Some(12));
+	}
+
+	#[test]
+	fn pop_ready_hands_out_blocks_in_height_order() {
+		let mut pipeline = pipeline(10, 8);
+		pipeline.reorder_buffer.insert(12, result(12));
+		pipeline.reorder_buffer.insert(11, result(11));
+		assert!(pipeline.pop_ready().is_none());
+
+		pipeline.reorder_buffer.insert(10, result(10));
+		let heights: Vec<u32> = std::iter::from_fn(|| pipeline.pop_ready())
+			.map(|x| x.block_height)
+			.collect();
+		assert_eq!(heights, vec![10, 11, 12]);
+	}
+
+	#[test]
+	fn transient_failure_is_retried() {
+		let mut pipeline = pipeline(10, 8);
+		let failure = pipeline.on_failure(10, TaskError::Transient(String::from("node down")));
+		assert_eq!(failure.attempt, 1);
+		assert!(failure.retry_in.is_some());
+		assert!(pipeline.retries.contains_key(&10));
+		assert!(pipeline.pop_ready().is_none());
+	}
+
+	#[test]
+	fn permanent_failure_is_quarantined_after_attempts() {
+		let mut pipeline = pipeline(10, 8);
+		for attempt in 1..PERMANENT_FAILURE_ATTEMPTS {
+			let failure = pipeline.on_failure(10, permanent());
+			assert_eq!(failure.attempt, attempt);
+			assert!(failure.retry_in.is_some());
+		}
+
+		let failure = pipeline.on_failure(10, permanent());
+		assert!(failure.retry_in.is_none());
+		let result = pipeline.pop_ready().unwrap();
+		assert_eq!(result.block_height, 10);
+		assert_eq!(result.db_data.quarantine_entries.len(), 1);
+		assert!(pipeline.attempts.is_empty());
+	}
+
+	#[test]
+	fn failing_block_is_quarantined_once_later_blocks_are_fetched() {
+		let mut pipeline = pipeline(10, 3);
+		assert_eq!(pipeline.next_height(100), Some(10));
+		assert_eq!(pipeline.next_height(100), Some(11));
+		assert_eq!(pipeline.next_height(100), Some(12));
+		pipeline.reorder_buffer.insert(11, result(11));
+
+		for _ in 1..TRANSIENT_FAILURE_ATTEMPTS {
+			let failure = pipeline.on_failure(10, TaskError::Transient(String::from("undecodable")));
+			assert!(failure.retry_in.is_some());
+			// Picked up again by the next `schedule`
+			pipeline.retries.clear();
+		}
+		let failure = pipeline.on_failure(10, TaskError::Transient(String::from("undecodable")));
+		assert!(failure.retry_in.is_none());
+		assert!(pipeline.retries.is_empty());
+
+		let quarantined = pipeline.pop_ready().unwrap();
+		assert_eq!(quarantined.block_height, 10);
+		assert_eq!(quarantined.db_data.quarantine_entries.len(), 1);
+		assert_eq!(pipeline.pop_ready().unwrap().block_height, 11);
+
+		// The window moved past the quarantined block.
+		assert_eq!(pipeline.next_height(100), Some(13));
+		assert_eq!(pipeline.next_height(100), Some(14));
+	}
+
+	#[test]
+	fn failing_block_is_retried_while_nothing_later_is_fetched() {
+		let mut pipeline = pipeline(10, 3);
+		for _ in 0..TRANSIENT_FAILURE_ATTEMPTS * 2 {
+			let failure = pipeline.on_failure(10, TaskError::Timeout(TASK_TIMEOUT));
+			assert!(failure.retry_in.is_some());
+		}
+		assert!(pipeline.pop_ready().is_none());
+	}
+
+	#[test]
+	fn schedule_without_clients_retries_the_height() {
+		let mut pipeline = pipeline(10, 3);
+		let failure = pipeline.schedule(100).unwrap();
+		assert_eq!(failure.block_height, 10);
+		assert!(matches!(failure.error, TaskError::Transient(_)));
+		assert!(failure.retry_in.is_some());
+		assert!(pipeline.retries.contains_key(&10));
+		assert!(pipeline.in_flight.is_empty());
+	}
+}
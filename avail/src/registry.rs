@@ -0,0 +1,168 @@
+//! Which calls are indexed and how.
+//!
+//! Every tracked (pallet, variant) pair is routed to one handler, and the handler decides which tables its rows
+//! go to. The Vector calls are always registered. Configuration can add more calls, either with one of the
+//! built-in decoders, which helps when a runtime upgrade moves a call to a new index, or with the `raw` decoder,
+//! which stores the SCALE encoded call in a table of its own. Other crates register their own handlers in code.
+
+use crate::{
+	configuration::TrackedCall,
+	handler::{
+		ExecuteHandler, ExtrinsicHandler, ProcessExtrinsic, RawCallHandler, SendMessageHandler, VectorFulfillHandler,
+	},
+};
+use std::{collections::BTreeMap, sync::Arc};
+
+#[derive(Clone)]
+pub(crate) struct RegisteredCall {
+	/// Used in logs and dead letter errors
+	pub name: String,
+	pub handler: Arc<dyn ProcessExtrinsic>,
+}
+
+#[derive(Clone)]
+pub struct CallRegistry {
+	calls: BTreeMap<(u8, u8), RegisteredCall>,
+}
+
+impl CallRegistry {
+	/// The built-in Vector handlers plus `tracked_calls`. A configured call replaces a built-in one with the same
+	/// pallet and variant. Two configured calls with the same pallet and variant are rejected.
+	pub fn new(tracked_calls: &[TrackedCall]) -> Result<Self, String> {
+		let mut registry = Self { calls: BTreeMap::new() };
+		registry.register(SendMessageHandler::default());
+		registry.register(ExecuteHandler::default());
+		// Fulfill is followed only for the Vector head updates that it emits.
+		registry.register(VectorFulfillHandler::default());
+
+		let mut configured = BTreeMap::new();
+		for call in tracked_calls {
+			let key = (call.pallet_id, call.variant_id);
+			if let Some(other) = configured.insert(key, &call.name) {
+				return Err(std::format!(
+					"Tracked calls {} and {} both use pallet_id {} and variant_id {}",
+					other,
+					call.name,
+					key.0,
+					key.1
+				));
+			}
+			registry.register_tracked_call(call)?;
+		}
+
+		Ok(registry)
+	}
+
+	/// Routes every call in the filter keys of `handler` to it. Whatever was registered for those calls before is
+	/// replaced.
+	pub fn register<H: ExtrinsicHandler>(&mut self, handler: H) {
+		let name = handler.name().to_string();
+		let keys = handler.filter_keys();
+		let handler: Arc<dyn ProcessExtrinsic> = Arc::new(handler);
+		for key in keys {
+			let call = RegisteredCall { name: name.clone(), handler: handler.clone() };
+			self.calls.insert(key, call);
+		}
+	}
+
+	fn register_tracked_call(&mut self, call: &TrackedCall) -> Result<(), String> {
+		let (name, key) = (call.name.clone(), (call.pallet_id, call.variant_id));
+		match (call.decoder.as_str(), &call.table_name) {
+			("send_message", _) => self.register(SendMessageHandler { name, key }),
+			("execute", _) => self.register(ExecuteHandler { name, key }),
+			("vector_fulfill", _) => self.register(VectorFulfillHandler { name, key }),
+			("raw", Some(table_name)) => self.register(RawCallHandler { name, key, table_name: table_name.clone() }),
+			("raw", None) => {
+				return Err(std::format!("Tracked call {} uses the raw decoder but has no table_name", call.name));
+			},
+			(other, _) => {
+				return Err(std::format!(
+					"Tracked call {} has unknown decoder: {}. Expected one of: send_message, execute, vector_fulfill, raw",
+					call.name,
+					other
+				));
+			},
+		}
+
+		Ok(())
+	}
+
+	pub(crate) fn get(&self, header: (u8, u8)) -> Option<&RegisteredCall> {
+		self.calls.get(&header)
+	}
+
+	/// (pallet_id, variant_id) of every registered call
+	pub fn keys(&self) -> Vec<(u8, u8)> {
+		self.calls.keys().copied().collect()
+	}
+
+	/// Call tables declared by the registered handlers
+	pub fn call_table_names(&self) -> Vec<String> {
+		let mut names: Vec<String> = self.calls.values().flat_map(|x| x.handler.call_tables()).collect();
+		names.sort();
+		names.dedup();
+		names
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use avail_rust::{
+		HasHeader,
+		avail::vector::tx::{Execute, Fulfill, SendMessage},
+	};
+
+	fn tracked_call(name: &str, key: (u8, u8), decoder: &str, table_name: Option<&str>) -> TrackedCall {
+		TrackedCall {
+			name: name.to_string(),
+			pallet_id: key.0,
+			variant_id: key.1,
+			decoder: decoder.to_string(),
+			table_name: table_name.map(|x| x.to_string()),
+		}
+	}
+
+	#[test]
+	fn new_registers_built_in_calls() {
+		let registry = CallRegistry::new(&[]).unwrap();
+		let mut expected = vec![SendMessage::HEADER_INDEX, Execute::HEADER_INDEX, Fulfill::HEADER_INDEX];
+		expected.sort();
+		assert_eq!(registry.keys(), expected);
+		assert!(registry.call_table_names().is_empty());
+	}
+
+	#[test]
+	fn new_adds_and_replaces_configured_calls() {
+		let calls = [
+			tracked_call("Vector::send_message_v2", SendMessage::HEADER_INDEX, "send_message", None),
+			tracked_call("System::remark", (0, 1), "raw", Some("remark_table")),
+		];
+		let registry = CallRegistry::new(&calls).unwrap();
+		assert_eq!(registry.keys().len(), 4);
+		assert_eq!(registry.get(SendMessage::HEADER_INDEX).unwrap().name, "Vector::send_message_v2");
+		assert_eq!(registry.get((0, 1)).unwrap().name, "System::remark");
+		assert_eq!(registry.call_table_names(), vec![String::from("remark_table")]);
+	}
+
+	#[test]
+	fn new_rejects_unknown_decoder() {
+		let calls = [tracked_call("System::remark", (0, 1), "remark", None)];
+		assert!(CallRegistry::new(&calls).is_err());
+	}
+
+	#[test]
+	fn new_rejects_raw_decoder_without_table() {
+		let calls = [tracked_call("System::remark", (0, 1), "raw", None)];
+		assert!(CallRegistry::new(&calls).is_err());
+	}
+
+	#[test]
+	fn new_rejects_duplicate_key() {
+		let calls = [
+			tracked_call("System::remark", (0, 1), "raw", Some("remark_table")),
+			tracked_call("System::remark_again", (0, 1), "raw", Some("other_table")),
+		];
+		assert!(CallRegistry::new(&calls).is_err());
+	}
+}
@@ -1,7 +1,10 @@
+use crate::concurrency::ConcurrencyController;
 use std::time::Instant;
 use tracing::info;
 
 pub struct IndexerStats {
+	/// Checkpoint name of the indexer. Tells the stats of concurrently running indexers apart.
+	pub name: String,
 	pub total_indexed: u32,
 	pub previously_indexed: u32,
 	pub checkpoint: Instant,
@@ -9,8 +12,9 @@ pub struct IndexerStats {
 }
 
 impl IndexerStats {
-	pub fn new(log_interval_ms: u32) -> Self {
+	pub fn new(name: String, log_interval_ms: u32) -> Self {
 		Self {
+			name,
 			total_indexed: 0,
 			previously_indexed: 0,
 			checkpoint: Instant::now(),
@@ -18,7 +22,14 @@ impl IndexerStats {
 		}
 	}
 
-	pub fn maybe_display_stats(&mut self, last_indexed_block: u32, finalized_block: u32, remaining_block_count: u32) {
+	pub fn maybe_display_stats(
+		&mut self,
+		last_indexed_block: u32,
+		finalized_block: u32,
+		remaining_block_count: u32,
+		task_count: u32,
+		concurrency: &ConcurrencyController,
+	) {
 		if !(self.checkpoint.elapsed().as_millis() > self.log_interval_ms) {
 			return;
 		}
@@ -30,12 +41,17 @@ impl IndexerStats {
 		self.previously_indexed = self.total_indexed;
 
 		info!(
+			indexer = self.name,
 			last_indexed_block,
 			remaining_block_count,
 			block_indexed_count,
 			finalized_block,
 			block_indexed_since_last_log_count,
 			bps,
+			task_count,
+			concurrency_limit = concurrency.limit(),
+			rpc_latency_ms = ?concurrency.last_latency().map(|x| x.as_millis() as u64),
+			rpc_error_rate = concurrency.last_error_rate(),
 			"📊 Indexing Stats"
 		);
 	}
@@ -0,0 +1,175 @@
+//! Compares what is stored for a range of heights against the chain and optionally rewrites the blocks that
+//! differ. Blocks are fetched the same way the indexer fetches them, so a repaired block is identical to one
+//! written by the live indexer. The checkpoint is never touched.
+//!
+//! A block that can't be fetched, compared or repaired is counted as failed and the walk goes on.
+
+use crate::{
+	configuration::Configuration,
+	db::Database,
+	endpoints::EndpointPool,
+	indexer::{TaskResult, create_task_params, spawn_tasks, update_task_params},
+	registry::CallRegistry,
+};
+use avail_rust::H256;
+use std::{
+	collections::{HashMap, HashSet},
+	sync::Arc,
+};
+use tracing::{info, warn};
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct VerifyReport {
+	pub checked_blocks: u32,
+	/// Blocks without a block table row
+	pub missing_blocks: u32,
+	/// Blocks whose stored hash differs from the chain
+	pub mismatched_blocks: u32,
+	/// Tracked extrinsics that are on chain but not in the main table
+	pub missing_rows: u32,
+	/// Main table rows at a height that don't belong to any tracked extrinsic of that block
+	pub extra_rows: u32,
+	/// Blocks with at least one of the differences above
+	pub differing_blocks: u32,
+	pub repaired_blocks: u32,
+	/// Blocks that could not be fetched, compared or repaired
+	pub failed_blocks: u32,
+}
+
+impl VerifyReport {
+	pub fn is_consistent(&self) -> bool {
+		self.differing_blocks == 0 && self.failed_blocks == 0
+	}
+
+	/// Every block was checked and every difference was repaired
+	pub fn is_resolved(&self) -> bool {
+		self.failed_blocks == 0 && self.repaired_blocks == self.differing_blocks
+	}
+}
+
+/// Walks `from..=to` and reports every difference. With `repair` set, blocks that differ are rewritten.
+/// `registry` has to hold the same handlers the indexer runs with, otherwise their rows are reported as extra.
+pub async fn run(
+	config: &Configuration,
+	registry: CallRegistry,
+	from: u32,
+	to: u32,
+	repair: bool,
+) -> Result<VerifyReport, String> {
+	let mut db = Database::from_config(config)
+		.await
+		.map_err(|e| std::format!("Failed to establish a connection with db. Reason: {}", e))?;
+	db.create_call_tables(registry.call_table_names()).await?;
+
+	let task_count = config.max_task_count.min((to - from).saturating_add(1)).max(1);
+	let endpoints = EndpointPool::connect(&config.avail_endpoints, config.rpc_connection_count).await?;
+	let mut task_params = create_task_params(&endpoints, task_count as usize, Arc::new(registry))?;
+	let mut handles = Vec::with_capacity(task_params.len());
+
+	info!(from, to, repair, task_count, "Verification started");
+
+	let mut report = VerifyReport::default();
+	let mut height = from;
+	while height <= to {
+		let last = to.min(height.saturating_add(task_count - 1));
+		task_params.truncate((last - height + 1) as usize);
+		update_task_params(height, &mut task_params);
+		spawn_tasks(&mut handles, &task_params);
+
+		let stored = find_stored(&db, height, last).await;
+		for (block_height, handle) in (height..=last).zip(handles.drain(..)) {
+			let result = match handle.await {
+				Ok(Ok(result)) => result,
+				Ok(Err(err)) => {
+					warn!(block_height, error = err.to_string(), "Failed to fetch block. Skipping it");
+					report.failed_blocks += 1;
+					continue;
+				},
+				Err(err) => {
+					warn!(block_height, error = err.to_string(), "Failed to fetch block. Skipping it");
+					report.failed_blocks += 1;
+					continue;
+				},
+			};
+
+			let (stored_blocks, stored_ids) = match &stored {
+				Ok(x) => x,
+				Err(err) => {
+					warn!(block_height, error = err, "Failed to read stored block. Skipping it");
+					report.failed_blocks += 1;
+					continue;
+				},
+			};
+			let stored_hash = stored_blocks.get(&block_height);
+			let stored_ids = stored_ids.get(&block_height).cloned().unwrap_or_default();
+			if let Err(err) = verify_block(&db, result, stored_hash, stored_ids, repair, &mut report).await {
+				warn!(block_height, error = err, "Failed to repair block. Skipping it");
+				report.failed_blocks += 1;
+			}
+		}
+
+		if last == u32::MAX {
+			break;
+		}
+		height = last + 1;
+	}
+
+	Ok(report)
+}
+
+/// Stored block hashes and main table ids of `from..=to` by height
+async fn find_stored(
+	db: &Database,
+	from: u32,
+	to: u32,
+) -> Result<(HashMap<u32, H256>, HashMap<u32, HashSet<u64>>), String> {
+	let stored_blocks: HashMap<u32, H256> = db.find_blocks(from, to).await?.into_iter().collect();
+	let mut stored_ids: HashMap<u32, HashSet<u64>> = HashMap::new();
+	for (block_height, id) in db.find_ext_ids(from, to).await? {
+		stored_ids.entry(block_height).or_default().insert(id);
+	}
+
+	Ok((stored_blocks, stored_ids))
+}
+
+async fn verify_block(
+	db: &Database,
+	result: TaskResult,
+	stored_hash: Option<&H256>,
+	stored_ids: HashSet<u64>,
+	repair: bool,
+	report: &mut VerifyReport,
+) -> Result<(), String> {
+	report.checked_blocks += 1;
+
+	let expected_ids: HashSet<u64> = result.db_data.main_entries.iter().map(|x| x.id).collect();
+	let missing_rows = expected_ids.difference(&stored_ids).count() as u32;
+	let extra_ids: Vec<u64> = stored_ids.difference(&expected_ids).copied().collect();
+	let missing_block = stored_hash.is_none();
+	let mismatched_block = stored_hash.is_some_and(|x| *x != result.block_hash);
+
+	if !missing_block && !mismatched_block && missing_rows == 0 && extra_ids.is_empty() {
+		return Ok(());
+	}
+
+	report.missing_blocks += missing_block as u32;
+	report.mismatched_blocks += mismatched_block as u32;
+	report.missing_rows += missing_rows;
+	report.extra_rows += extra_ids.len() as u32;
+	report.differing_blocks += 1;
+	warn!(
+		block_height = result.block_height,
+		missing_block,
+		mismatched_block,
+		missing_rows,
+		extra_rows = extra_ids.len(),
+		"Stored block differs from chain"
+	);
+
+	if repair {
+		db.repair(&result.db_data, &extra_ids).await?;
+		report.repaired_blocks += 1;
+	}
+
+	Ok(())
+}